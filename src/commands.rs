@@ -1,32 +1,61 @@
-use super::generation::PageGenerator;
-use super::config::Config;
+use super::extensions::TemplateExtensions;
+use super::external::ExternalPlugin;
+use super::plugins::{built_in_plugins, plugin_error, Plugin};
+use super::scripts::{Scripts, SCRIPTS_DIR};
+use super::site::Site;
+use super::vfs::{DiskFileSystem, FileSystem};
+use super::config::{Config, ConfigFormat};
 
 use std::io;
 use std::io::{Read, Write};
 use std::fs;
 use std::fs::{DirBuilder, File};
-use std::path::Path;
-use std::error::Error;
-use std::fs::OpenOptions;
-
-use hyper::server::{Request, Response, Server};
+use std::path::{Path, PathBuf};
+use std::io::BufWriter;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use hyper::Error as HyperError;
+use hyper::header::{AcceptRanges, ByteRangeSpec, Connection, ContentLength, ContentRange, ContentRangeSpec, ETag, EntityTag,
+                    Headers, HttpDate, IfModifiedSince, IfNoneMatch, LastModified, Location, Range, RangeUnit};
+use hyper::buffer::BufReader;
+use hyper::net::{HttpStream, NetworkStream};
+use hyper::server::{Request, Response};
 use hyper::status::StatusCode;
 use hyper::uri::RequestUri;
 use hyper::method::Method;
 
-use walkdir::WalkDir;
-
-use pulldown_cmark::{Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_FOOTNOTES};
+use percent_encoding::percent_decode;
 
 const DEFAULT_CONFIG_FILE: &'static str = "\
 source: pages
 output: _site
+host: 127.0.0.1
 port: 4000
 markdown_options:
   - tables
   - footnotes
 ";
 
+const DEFAULT_TOML_CONFIG_FILE: &'static str = "\
+source = \"pages\"
+output = \"_site\"
+host = \"127.0.0.1\"
+port = 4000
+markdown_options = [\"tables\", \"footnotes\"]
+";
+
+type Plugins = Vec<Box<dyn Plugin>>;
+
+lazy_static! {
+    /// Where the Ctrl-C handler reports to, for the `serve` that's running.
+    static ref CTRL_C: Mutex<Option<Sender<()>>> = Mutex::new(None);
+}
+
 const BAD_REQUEST: &'static str = "\
 <h1>400 Bad Request</h1>
 ";
@@ -35,86 +64,123 @@ const NOT_FOUND: &'static str = "\
 <h1>404 Not Found</h1>
 ";
 
-pub fn new_project(parent_dir: &str) -> Result<(), io::Error> {
+pub fn new_project(parent_dir: &str, config_format: ConfigFormat) -> Result<(), io::Error> {
     DirBuilder::new().recursive(true).create(parent_dir)?;
 
     DirBuilder::new().recursive(false).create(format!("{}/pages", parent_dir))?;
 
-    let mut config_file = File::create(format!("{}/_config.yml", parent_dir))?;
+    let mut config_file = File::create(format!("{}/{}", parent_dir, config_format.file_name()))?;
+
+    let config_contents = match config_format {
+        ConfigFormat::Yaml => DEFAULT_CONFIG_FILE,
+        ConfigFormat::Toml => DEFAULT_TOML_CONFIG_FILE
+    };
 
-    config_file.write_all(DEFAULT_CONFIG_FILE.as_bytes())?;
+    config_file.write_all(config_contents.as_bytes())?;
 
     Ok(())
 }
 
 pub fn build_project(config: &Config) -> Result<(), io::Error> {
-    let pages_path = &*config.source_dir;
-    let output_dir = &*config.output_dir;
-    let mut markdown_options = Options::empty();
+    build_project_with_extensions(config, &TemplateExtensions::new())
+}
 
-    if config.markdown_options.contains(&"footnotes".to_string()) {
-        markdown_options.insert(OPTION_ENABLE_FOOTNOTES);
-    }
+/// Builds the site like `build_project`, making custom Liquid filters, tags and blocks available
+/// to every page.
+pub fn build_project_with_extensions(config: &Config, extensions: &TemplateExtensions) -> Result<(), io::Error> {
+    build_project_with_plugins(config, extensions, Vec::new())
+}
 
-    if config.markdown_options.contains(&"tables".to_string()) {
-        markdown_options.insert(OPTION_ENABLE_TABLES);
-    }
+/// Builds the site like `build_project_with_extensions`, running `plugins` after the built-in and
+/// external plugins named in the config and the scripts in `_scripts`. Those are picked once
+/// `plugins` have had their `after_config` hooks, so they can change which run.
+pub fn build_project_with_plugins(config: &Config, extensions: &TemplateExtensions, plugins: Vec<Box<dyn Plugin>>) -> Result<(), io::Error> {
+    build_project_from_file_system(config, extensions, plugins, Arc::new(DiskFileSystem))
+}
+
+/// Builds the site like `build_project_with_plugins`, reading the pages, layouts, data and scripts
+/// from `file_system` rather than the disk, e.g. to preview unsaved edits. The output is still
+/// written to the config's output directory.
+pub fn build_project_from_file_system(config: &Config, extensions: &TemplateExtensions, plugins: Vec<Box<dyn Plugin>>,
+                                      file_system: Arc<dyn FileSystem>) -> Result<(), io::Error> {
+    let (config, extensions, mut plugins) = set_up_plugins(config, extensions, plugins, file_system.as_ref())?;
 
-    let mut page_generator = PageGenerator::new();
+    let mut site = Site::load_from_file_system(&config, &extensions, file_system)?;
+
+    for plugin in &mut plugins {
+        plugin.generate(&mut site).map_err(|what| plugin_error(plugin.as_ref(), what))?;
+    }
 
-    let directory_iterator = WalkDir::new(pages_path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file() &&
-            !e.file_name().to_str().unwrap().starts_with("_") &&
-            !(e.path().to_str().unwrap().contains("/_") || e.path().to_str().unwrap().contains("\\_")));
+    let output = site.render_with_plugins(&mut plugins)?;
+    site.write(&output)?;
 
-    if !Path::new(output_dir).exists() {
-        DirBuilder::new().create(output_dir)?;
+    for plugin in &mut plugins {
+        plugin.after_build(&site, Path::new(&config.output_dir)).map_err(|what| plugin_error(plugin.as_ref(), what))?;
     }
 
-    for file in directory_iterator {
-        // Name of the file?
-        let file_name = file.file_name().to_str().unwrap().to_string();
+    Ok(())
+}
 
-        let destination_file = format!("{}/{}", output_dir, file
-            .path()
-            .strip_prefix(pages_path)
-            .unwrap()
-            .with_extension("html")
-            .display()
-        );
+/// Renders one page of the project, wrapped in its layouts, as it would be built. `file` is a
+/// Markdown file in the source directory; it's rendered even if it's a draft or dated in the
+/// future. No other page is parsed. Plugins' `before_render` and `after_render` hooks run, but
+/// nothing is written.
+pub fn render_file(config: &Config, file: &Path) -> Result<String, io::Error> {
+    let (config, extensions, mut plugins) = set_up_plugins(config, &TemplateExtensions::new(), Vec::new(), &DiskFileSystem)?;
+
+    let source_dir = fs::canonicalize(&config.source_dir)?;
+    let file = fs::canonicalize(file).map_err(|what| io::Error::new(what.kind(), format!("Couldn't read {}: {}", file.display(), what)))?;
+    let source_path = file.strip_prefix(&source_dir).ok().and_then(|source_path| source_path.to_str()).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't in the source directory {}", file.display(), source_dir.display()))
+    })?;
+
+    let mut source = String::new();
+    File::open(&file)
+        .and_then(|mut input| input.read_to_string(&mut source))
+        .map_err(|what| io::Error::new(what.kind(), format!("Couldn't read {}: {}", file.display(), what)))?;
+
+    let site = Site::load_without_pages(&config, &extensions, Arc::new(DiskFileSystem))?;
+    let mut page = site.parse_page(source_path, &source)?;
+
+    for plugin in &mut plugins {
+        plugin.before_render(&mut page).map_err(|what| plugin_error(plugin.as_ref(), what))?;
+    }
 
-        fs::create_dir_all(Path::new(&destination_file).parent().unwrap()).unwrap();
+    let mut html = site.render_page(&page)?;
 
-        if file_name.contains(".md") {
-            let parsed = page_generator.set_input_file(file.path().to_str().expect("Couldn't convert for some reason"))
-                .set_output_file(destination_file.as_str())
-                .set_parse_options(markdown_options.clone())
-                .parse_file()?
-                .render_to_string()?;
+    for plugin in &mut plugins {
+        plugin.after_render(&page, &mut html).map_err(|what| plugin_error(plugin.as_ref(), what))?;
+    }
 
-            let mut output_file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(destination_file.as_str())?;
+    Ok(html)
+}
 
-            output_file.write_all(&parsed.as_bytes())?;
-        } else if file_name.contains(".html") {
-            let output_file_name = format!("{}/{}", config.output_dir, file_name);
-            let output_file_path = Path::new(&output_file_name);
+/// Runs `after_config` for the library's plugins and then the built-in and external ones, and
+/// loads the project's scripts from `file_system`. Returns the updated config, the extensions with the scripts'
+/// filters added, and every plugin in the order their hooks run.
+fn set_up_plugins(config: &Config, extensions: &TemplateExtensions, mut plugins: Plugins, file_system: &dyn FileSystem)
+                  -> Result<(Config, TemplateExtensions, Plugins), io::Error> {
+    let mut config = config.clone();
+    for plugin in &mut plugins {
+        plugin.after_config(&mut config).map_err(|what| plugin_error(plugin.as_ref(), what))?;
+    }
 
-            fs::copy(file.path(), output_file_path).unwrap();
-        } else {
-            let output_file_name = format!("{}/{}", config.output_dir, file_name);
-            let output_file_path = Path::new(&output_file_name);
+    let mut built_in = built_in_plugins(&config)?;
+    built_in.extend(config.external_plugins.iter()
+        .map(|external| Box::new(ExternalPlugin::new(external.clone())) as Box<dyn Plugin>));
+    for plugin in &mut built_in {
+        plugin.after_config(&mut config).map_err(|what| plugin_error(plugin.as_ref(), what))?;
+    }
 
-            fs::copy(file.path(), output_file_path).unwrap();
-        }
+    let mut extensions = extensions.clone();
+    let scripts = Scripts::load(file_system, &Path::new(&config.source_dir).join(SCRIPTS_DIR))?;
+    if !scripts.is_empty() {
+        scripts.add_filters(&mut extensions);
+        built_in.push(Box::new(scripts));
     }
 
-    Ok(())
+    let plugins = built_in.into_iter().chain(plugins).collect();
+    Ok((config, extensions, plugins))
 }
 
 pub fn clean_project(config: &Config) -> Result<(), io::Error> {
@@ -124,60 +190,413 @@ pub fn clean_project(config: &Config) -> Result<(), io::Error> {
 }
 
 pub fn serve(config: &Config) -> Result<(), io::Error> {
-    build_project(&config)?;
+    serve_until(config, listen_for_ctrl_c()?)
+}
 
-    let server_addr = format!("127.0.0.1:{}", &*config.port);
-    let server = match Server::http(server_addr.as_str()) {
-        Ok(server) => server,
-        Err(what) => panic!("{}", Error::description(&what))
-    };
+/// A channel that's told when Ctrl-C is pressed. The handler can only be installed once per
+/// process, so later calls hand the existing one a new sender instead.
+fn listen_for_ctrl_c() -> Result<Receiver<()>, io::Error> {
+    let (sender, receiver) = mpsc::channel();
+    *CTRL_C.lock().expect("Ctrl-C sender poisoned") = Some(sender);
 
-    let serve_dir = config.output_dir.clone();
-    match server.handle(move |request: Request, response: Response| {
-        match handle_static_file(&serve_dir, request, response) {
-            Ok(_) => {}
-            Err(what) => panic!("{}", Error::description(&what))
+    let installed = ::ctrlc::set_handler(|| {
+        if let Some(ref sender) = *CTRL_C.lock().expect("Ctrl-C sender poisoned") {
+            sender.send(()).ok();
         }
-    }) {
-        Ok(_) => {}
-        Err(what) => panic!("{}", Error::description(&what))
+    });
+
+    match installed {
+        Ok(()) | Err(::ctrlc::Error::MultipleHandlers) => Ok(receiver),
+        Err(what) => Err(io::Error::other(format!("Couldn't listen for Ctrl-C: {}", what)))
     }
+}
 
-    Ok(())
+/// Builds the project and serves it until a message arrives on `shutdown` or its sender is dropped.
+pub fn serve_until(config: &Config, shutdown: Receiver<()>) -> Result<(), io::Error> {
+    build_project(config)?;
+
+    let server = DevServer::start(config)?;
+    println!("Serving project on http://{}{}/", server.local_addr(), config.base_path);
+
+    shutdown.recv().ok();
+
+    println!("Shutting down...");
+    server.shutdown()
+}
+
+/// A running development server for a built site.
+///
+/// Each connection is handled on its own thread and answers a single request. Dropping the server
+/// shuts it down, waiting for any requests that are still in flight.
+pub struct DevServer {
+    local_addr: SocketAddr,
+    running: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
 }
 
-fn handle_static_file(page_dir: &str, request: Request, mut response: Response) -> Result<(), io::Error> {
-    let path = match request.uri {
-        RequestUri::AbsolutePath(ref uri) if request.method == Method::Get => {
-            if uri == "/" {
-                "/index.html"
-            } else {
-                uri
+impl DevServer {
+    /// Binds to the configured host and port and starts serving `config.output_dir`.
+    pub fn start(config: &Config) -> Result<Self, io::Error> {
+        let server_addr = if config.host.contains(':') {
+            format!("[{}]:{}", config.host, config.port)
+        } else {
+            format!("{}:{}", config.host, config.port)
+        };
+        let listener = match TcpListener::bind((config.host.as_str(), config.port)) {
+            Ok(listener) => listener,
+            Err(ref what) if what.kind() == io::ErrorKind::AddrInUse => {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("Address {} is already in use", server_addr)))
+            },
+            Err(what) => {
+                return Err(io::Error::new(what.kind(), format!("Couldn't listen on {}: {}", server_addr, what)))
+            }
+        };
+
+        let local_addr = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+
+        let serve_dir = config.output_dir.clone();
+        let base_path = config.base_path.clone();
+        let log_format = config.log_format.clone();
+        let acceptor_running = running.clone();
+        let acceptor = thread::spawn(move || {
+            let mut connections: Vec<JoinHandle<()>> = Vec::new();
+
+            for stream in listener.incoming() {
+                if !acceptor_running.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(what) => {
+                        writeln!(io::stderr(), "Error accepting connection: {}", what).ok();
+                        continue;
+                    }
+                };
+
+                let serve_dir = serve_dir.clone();
+                let base_path = base_path.clone();
+                let log_format = log_format.clone();
+                connections.retain(|connection| !connection.is_finished());
+                connections.push(thread::spawn(move || {
+                    if let Err(what) = handle_connection(&serve_dir, &base_path, &log_format, stream) {
+                        writeln!(io::stderr(), "Error handling request: {}", what).ok();
+                    }
+                }));
             }
+
+            for connection in connections {
+                connection.join().ok();
+            }
+        });
+
+        Ok(DevServer {
+            local_addr: local_addr,
+            running: running,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops accepting connections and waits for in-flight requests to finish.
+    pub fn shutdown(mut self) -> Result<(), io::Error> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<(), io::Error> {
+        let acceptor = match self.acceptor.take() {
+            Some(acceptor) => acceptor,
+            None => return Ok(())
+        };
+
+        self.running.store(false, Ordering::SeqCst);
+
+        // The acceptor is blocked waiting for a connection, so give it one to wake it up
+        let wake_addr = if self.local_addr.ip().is_unspecified() && self.local_addr.is_ipv6() {
+            SocketAddr::new(Ipv6Addr::LOCALHOST.into(), self.local_addr.port())
+        } else if self.local_addr.ip().is_unspecified() {
+            SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), self.local_addr.port())
+        } else {
+            self.local_addr
+        };
+        TcpStream::connect(wake_addr).ok();
+
+        acceptor.join().map_err(|_| io::Error::other("Server thread panicked"))
+    }
+}
+
+impl Drop for DevServer {
+    fn drop(&mut self) {
+        self.stop().ok();
+    }
+}
+
+fn handle_connection(serve_dir: &str, base_path: &str, log_format: &str, stream: TcpStream) -> Result<(), io::Error> {
+    let started = Instant::now();
+    let remote_addr = stream.peer_addr()?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut network_stream = HttpStream(stream.try_clone()?);
+    let mut reader = BufReader::new(&mut network_stream as &mut dyn NetworkStream);
+    let request = Request::new(&mut reader, remote_addr);
+
+    let mut writer = BufWriter::new(stream);
+    let mut headers = Headers::new();
+    headers.set(Connection::close());
+    let mut response = Response::new(&mut writer, &mut headers);
+
+    let (method, path, (status, bytes)) = match request {
+        Ok(request) => {
+            let method = request.method.to_string();
+            let path = request.uri.to_string();
+
+            (method, path, handle_static_file(serve_dir, base_path, request, response)?)
         },
-        _ => {
+        Err(HyperError::Io(what)) => return Err(what),
+        Err(_) => {
             *response.status_mut() = StatusCode::BadRequest;
-            let body = BAD_REQUEST.as_bytes();
-            response.send(body)?;
-            return Ok(())
+
+            ("-".to_string(), "-".to_string(), send_body(response, BAD_REQUEST.as_bytes(), false)?)
         }
     };
 
-    let file_path = Path::new(page_dir).join(&path[1..]);
+    if !log_format.is_empty() {
+        println!("{}", format_request_log(log_format, &method, &path, status, bytes, started.elapsed()));
+    }
+
+    Ok(())
+}
+
+/// Fills in the `{method}`, `{path}`, `{status}`, `{bytes}` and `{duration}` placeholders of a
+/// request log format. The duration is given in milliseconds.
+fn format_request_log(log_format: &str, method: &str, path: &str, status: StatusCode, bytes: u64, duration: Duration) -> String {
+    let milliseconds = duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0;
+
+    log_format
+        .replace("{method}", method)
+        .replace("{path}", path)
+        .replace("{status}", &status.to_u16().to_string())
+        .replace("{bytes}", &bytes.to_string())
+        .replace("{duration}", &format!("{:.2}", milliseconds))
+}
 
-    if file_path.exists() && file_path.is_file() {
-        let mut file = File::open(file_path)?;
-        let mut file_contents = String::new();
+fn handle_static_file(page_dir: &str, base_path: &str, request: Request, mut response: Response) -> Result<(StatusCode, u64), io::Error> {
+    let head_only = request.method == Method::Head;
+    let uri = match request.uri {
+        RequestUri::AbsolutePath(ref uri) if request.method == Method::Get || head_only => uri.as_str(),
+        _ => {
+            *response.status_mut() = StatusCode::BadRequest;
+            return send_body(response, BAD_REQUEST.as_bytes(), head_only)
+        }
+    };
 
-        file.read_to_string(&mut file_contents)?;
+    let request_path = uri.split(&['?', '#'][..]).next().unwrap_or("");
+    if !base_path.is_empty() && (request_path == "/" || request_path == base_path) {
+        *response.status_mut() = StatusCode::Found;
+        response.headers_mut().set(Location(format!("{}/", base_path)));
+        return send_body(response, &[], head_only)
+    }
 
-        *response.status_mut() = StatusCode::Ok;
-        response.send(&file_contents.into_bytes())?;
-        return Ok(())
+    let site_path = if uri.starts_with(&format!("{}/", base_path)) {
+        &uri[base_path.len()..]
     } else {
         *response.status_mut() = StatusCode::NotFound;
-        let body = NOT_FOUND.as_bytes();
+        return send_body(response, NOT_FOUND.as_bytes(), head_only)
+    };
+
+    let file_path = match resolve_request_path(page_dir, site_path) {
+        Some(file_path) => file_path,
+        None => {
+            *response.status_mut() = StatusCode::BadRequest;
+            return send_body(response, BAD_REQUEST.as_bytes(), head_only)
+        }
+    };
+
+    if !(file_path.exists() && file_path.is_file()) {
+        *response.status_mut() = StatusCode::NotFound;
+        return send_body(response, NOT_FOUND.as_bytes(), head_only)
+    }
+
+    let metadata = fs::metadata(&file_path)?;
+    let last_modified = metadata.modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let entity_tag = EntityTag::strong(format!("{:x}-{:x}", last_modified, metadata.len()));
+
+    response.headers_mut().set(ETag(entity_tag.clone()));
+    response.headers_mut().set(LastModified(HttpDate(::time::at_utc(::time::Timespec::new(last_modified as i64, 0)))));
+    response.headers_mut().set(AcceptRanges(vec![RangeUnit::Bytes]));
+
+    if is_not_modified(&request.headers, &entity_tag, last_modified) {
+        *response.status_mut() = StatusCode::NotModified;
+        response.start()?.end()?;
+        return Ok((StatusCode::NotModified, 0))
+    }
+
+    let mut file_contents = Vec::new();
+    File::open(file_path)?.read_to_end(&mut file_contents)?;
+
+    let file_length = file_contents.len() as u64;
+    let body = match requested_range(&request.headers, file_length) {
+        Some(Ok((first, last))) => {
+            *response.status_mut() = StatusCode::PartialContent;
+            response.headers_mut().set(ContentRange(ContentRangeSpec::Bytes {
+                range: Some((first, last)),
+                instance_length: Some(file_length)
+            }));
+
+            &file_contents[first as usize..(last + 1) as usize]
+        },
+        Some(Err(())) => {
+            *response.status_mut() = StatusCode::RangeNotSatisfiable;
+            response.headers_mut().set(ContentRange(ContentRangeSpec::Bytes {
+                range: None,
+                instance_length: Some(file_length)
+            }));
+
+            &[]
+        },
+        None => {
+            *response.status_mut() = StatusCode::Ok;
+            &file_contents[..]
+        }
+    };
+
+    send_body(response, body, head_only)
+}
+
+/// Sends `body` with the response's current status, leaving the body off for `HEAD` requests.
+/// Returns the status along with the number of body bytes written.
+fn send_body(mut response: Response, body: &[u8], head_only: bool) -> Result<(StatusCode, u64), io::Error> {
+    let status = response.status();
+
+    if head_only {
+        response.headers_mut().set(ContentLength(body.len() as u64));
+        response.start()?.end()?;
+
+        Ok((status, 0))
+    } else {
         response.send(body)?;
-        return Ok(())
+
+        Ok((status, body.len() as u64))
+    }
+}
+
+/// Checks the request's conditional headers against the file's current validators.
+///
+/// `If-None-Match` takes precedence, so `If-Modified-Since` is only consulted when the client
+/// didn't send an entity tag.
+fn is_not_modified(headers: &Headers, entity_tag: &EntityTag, last_modified: u64) -> bool {
+    if let Some(if_none_match) = headers.get::<IfNoneMatch>() {
+        return match *if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(ref tags) => tags.iter().any(|tag| tag.weak_eq(entity_tag))
+        };
+    }
+
+    match headers.get::<IfModifiedSince>() {
+        Some(&IfModifiedSince(HttpDate(ref since))) => since.to_timespec().sec >= last_modified as i64,
+        None => false
+    }
+}
+
+/// Resolves a single byte range from the `Range` header into inclusive offsets.
+///
+/// Returns `None` when the whole file should be sent, which includes requests for multiple
+/// ranges, and `Some(Err(()))` when the requested range lies outside of the file.
+fn requested_range(headers: &Headers, file_length: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = match headers.get::<Range>() {
+        Some(Range::Bytes(specs)) if specs.len() == 1 => &specs[0],
+        _ => return None
+    };
+
+    let range = match *spec {
+        ByteRangeSpec::FromTo(first, last) if first > last => return None,
+        ByteRangeSpec::FromTo(first, last) => (first, last.min(file_length.saturating_sub(1))),
+        ByteRangeSpec::AllFrom(first) => (first, file_length.saturating_sub(1)),
+        ByteRangeSpec::Last(0) => return Some(Err(())),
+        ByteRangeSpec::Last(count) => (file_length.saturating_sub(count), file_length.saturating_sub(1))
+    };
+
+    if file_length == 0 || range.0 >= file_length {
+        Some(Err(()))
+    } else {
+        Some(Ok(range))
+    }
+}
+
+/// Maps a raw request path onto a file beneath `page_dir`.
+///
+/// The query string and fragment are dropped and the remaining path is percent-decoded before
+/// `.` and `..` segments are resolved. Returns `None` for paths that can't be decoded or that
+/// would resolve to somewhere outside of `page_dir`.
+fn resolve_request_path(page_dir: &str, uri: &str) -> Option<PathBuf> {
+    let raw_path = uri.split(&['?', '#'][..]).next().unwrap_or("");
+    let decoded_path = match percent_decode(raw_path.as_bytes()).decode_utf8() {
+        Ok(decoded_path) => decoded_path,
+        Err(_) => return None
+    };
+
+    let mut segments = Vec::new();
+    for segment in decoded_path.split(&['/', '\\'][..]) {
+        match segment {
+            "" | "." => {},
+            ".." => {
+                segments.pop()?;
+            },
+            segment if segment.contains('\0') || segment.contains(':') => return None,
+            segment => segments.push(segment)
+        }
+    }
+
+    let mut file_path = PathBuf::from(page_dir);
+    file_path.extend(segments);
+
+    if file_path.is_dir() {
+        file_path.push("index.html");
+    }
+
+    // Symlinks inside the output directory could still point elsewhere on disk
+    if let (Ok(canonical_file), Ok(canonical_dir)) = (file_path.canonicalize(), Path::new(page_dir).canonicalize()) {
+        if !canonical_file.starts_with(canonical_dir) {
+            return None;
+        }
+    }
+
+    Some(file_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_listens_for_ctrl_c_more_than_once() {
+        listen_for_ctrl_c().expect("Listen for Ctrl-C");
+        let receiver = listen_for_ctrl_c().expect("Listen for Ctrl-C again");
+
+        CTRL_C.lock().unwrap().as_ref().expect("Sender").send(()).expect("Send");
+        assert_eq!(Ok(()), receiver.try_recv());
+    }
+
+    #[test]
+    fn it_formats_request_log_lines() {
+        let line = format_request_log("{method} {path} {status} {bytes}B {duration}ms",
+                                      "GET", "/index.html", StatusCode::Ok, 512, Duration::from_millis(3));
+
+        assert_eq!("GET /index.html 200 512B 3.00ms", line);
+    }
+
+    #[test]
+    fn it_leaves_unknown_placeholders_in_request_log_lines() {
+        let line = format_request_log("[{remote}] {status}", "GET", "/", StatusCode::NotFound, 0, Duration::from_millis(0));
+
+        assert_eq!("[{remote}] 404", line);
     }
 }
@@ -1,42 +1,506 @@
-use std::error::Error;
-use yaml_rust::yaml::YamlLoader;
+use std::io;
+use std::io::Read;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use yaml_rust::yaml::{Yaml, YamlLoader};
+use yaml_rust::emitter::YamlEmitter;
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::Marker;
+use strsim::{jaro_winkler, levenshtein};
+use toml;
+use super::external::{DEFAULT_TIMEOUT, HOOKS};
+use super::generation::DEFAULT_EXCERPT_SEPARATOR;
+use glob::{MatchOptions, Pattern};
 
+/// The name of the configuration file at the root of a project.
+pub const CONFIG_FILE_NAME: &'static str = "_config.yml";
+
+/// Every config file name aluminum recognizes, in the order they're looked for.
+pub const CONFIG_FILE_NAMES: &'static [&'static str] = &[CONFIG_FILE_NAME, "_config.toml", "aluminum.toml"];
+
+/// The environment used when neither `--env` nor `ALUMINUM_ENV` picks one.
+pub const DEFAULT_ENVIRONMENT: &'static str = "development";
+
+/// Prefix for environment variables that override config keys, e.g. `ALUMINUM_OUTPUT`.
+pub const ENV_OVERRIDE_PREFIX: &'static str = "ALUMINUM_";
+
+/// The keys aluminum reads from the config, and the type each expects. Any other key is kept as
+/// custom site data for templates, unless it looks like a misspelling of one of these.
+const KNOWN_KEYS: &'static [(&'static str, ValueType)] = &[
+    ("source", ValueType::String),
+    ("output", ValueType::String),
+    ("host", ValueType::String),
+    ("port", ValueType::Port),
+    ("log_format", ValueType::String),
+    ("base_url", ValueType::String),
+    ("base_path", ValueType::String),
+    ("markdown_options", ValueType::StringList),
+    ("environment", ValueType::String),
+    ("drafts", ValueType::Bool),
+    ("future", ValueType::Bool),
+    ("defaults", ValueType::Defaults),
+    ("excerpt_separator", ValueType::String),
+    ("git_dates", ValueType::Bool),
+    ("plugins", ValueType::StringList),
+    ("external_plugins", ValueType::ExternalPlugins),
+];
+
+/// The formats a config file can be written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml
+}
+
+impl ConfigFormat {
+    /// Picks the format from a file's extension, treating anything but `.toml` as YAML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Yaml
+        }
+    }
+
+    /// The file name `aluminum new` writes a config in this format to.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ConfigFormat::Yaml => CONFIG_FILE_NAME,
+            ConfigFormat::Toml => "_config.toml"
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "yaml" | "yml" => Ok(ConfigFormat::Yaml),
+            "toml" => Ok(ConfigFormat::Toml),
+            _ => Err(format!("Unknown config format \"{}\": expected yaml or toml", format))
+        }
+    }
+}
+
+/// Front matter values applied to every page a scope matches, from the `defaults` section:
+///
+/// ```yaml
+/// defaults:
+///   - scope:
+///       path: docs
+///       type: pages
+///     values:
+///       layout: doc
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrontMatterDefault {
+    /// A path relative to the source directory, either a prefix like `docs` or a glob like
+    /// `docs/**/*.md`. Empty matches every page.
+    pub path: String,
+    /// The type of page to match, or `None` for any type. See `page_type` in the build for how
+    /// a page's type is decided.
+    pub page_type: Option<String>,
+    pub values: Yaml
+}
+
+impl FrontMatterDefault {
+    fn from_yaml(yaml: &Yaml) -> Self {
+        FrontMatterDefault {
+            path: yaml["scope"]["path"].as_str().unwrap_or("").trim_matches('/').to_string(),
+            page_type: yaml["scope"]["type"].as_str().map(|page_type| page_type.to_string()),
+            values: yaml["values"].clone()
+        }
+    }
+
+    /// Whether the scope covers a page at `path`, relative to the source directory.
+    pub fn matches(&self, path: &Path, page_type: &str) -> bool {
+        if let Some(ref scope_type) = self.page_type {
+            if scope_type != page_type {
+                return false;
+            }
+        }
+
+        let path = path.to_string_lossy().replace('\\', "/");
+
+        if self.path.is_empty() {
+            true
+        } else if self.path.contains(&['*', '?', '['][..]) {
+            let options = MatchOptions { require_literal_separator: true, ..MatchOptions::new() };
+            Pattern::new(&self.path).map(|pattern| pattern.matches_with(&path, options)).unwrap_or(false)
+        } else {
+            path == self.path || path.starts_with(&format!("{}/", self.path))
+        }
+    }
+}
+
+/// A plugin run as a separate program, from the `external_plugins` section:
+///
+/// ```yaml
+/// external_plugins:
+///   - name: tags
+///     command: python3 _plugins/tags.py
+///     hooks: [generate, after_render]
+///     timeout: 10
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExternalPluginConfig {
+    pub name: String,
+    /// Run by the shell from the project root, `Config::root_dir`.
+    pub command: String,
+    /// The build hooks the command is run for.
+    pub hooks: Vec<String>,
+    /// How many seconds each run may take before it's stopped.
+    pub timeout: u64
+}
+
+impl ExternalPluginConfig {
+    fn from_yaml(yaml: &Yaml) -> Self {
+        ExternalPluginConfig {
+            name: yaml["name"].as_str().unwrap_or("").to_string(),
+            command: yaml["command"].as_str().unwrap_or("").to_string(),
+            hooks: yaml["hooks"].as_vec()
+                .map(|hooks| hooks.iter().filter_map(|hook| hook.as_str().map(|hook| hook.to_string())).collect())
+                .unwrap_or_default(),
+            timeout: yaml["timeout"].as_i64().map(|timeout| timeout as u64).unwrap_or(DEFAULT_TIMEOUT)
+        }
+    }
+
+    fn to_yaml(&self) -> Yaml {
+        let mut hash = ::yaml_rust::yaml::Hash::new();
+        hash.insert(Yaml::String("name".to_string()), Yaml::String(self.name.clone()));
+        hash.insert(Yaml::String("command".to_string()), Yaml::String(self.command.clone()));
+        hash.insert(Yaml::String("hooks".to_string()), Yaml::Array(self.hooks.iter().cloned().map(Yaml::String).collect()));
+        hash.insert(Yaml::String("timeout".to_string()), Yaml::Integer(self.timeout as i64));
+
+        Yaml::Hash(hash)
+    }
+}
+
+#[derive(Clone)]
 pub struct Config {
     pub source_dir: String,
     pub output_dir: String,
-    pub port: String,
-    pub markdown_options: Vec<String>
+    pub host: String,
+    pub port: u16,
+    pub log_format: String,
+    pub base_url: String,
+    pub base_path: String,
+    pub markdown_options: Vec<String>,
+    pub environment: String,
+    /// Whether to build drafts: pages marked `draft: true` and anything under `_drafts`.
+    pub drafts: bool,
+    /// Whether to build pages whose `date` is still in the future.
+    pub future: bool,
+    /// Front matter defaults, in the order they're applied.
+    pub defaults: Vec<FrontMatterDefault>,
+    /// Marks the end of a page's excerpt; pages without one use their first paragraph.
+    pub excerpt_separator: String,
+    /// Whether to date pages by their git history instead of file modification times.
+    pub git_dates: bool,
+    /// Built-in plugins to run, such as `sitemap` and `feed`.
+    pub plugins: Vec<String>,
+    /// Plugins run as separate programs, which talk to the build in JSON.
+    pub external_plugins: Vec<ExternalPluginConfig>,
+    /// The merged configuration document, exposed to templates as `site`.
+    pub site: Yaml,
+    /// The project root, which external plugin commands are run from. It isn't read from the
+    /// config; the command line sets it to the directory the config was found in.
+    pub root_dir: String
 }
 
 impl Config {
-    pub fn from_string(config_string: String) -> Self {
-        let yaml = match YamlLoader::load_from_str(&config_string) {
-            Ok(yaml) => yaml,
-            Err(what) => panic!("Config file couldn't be read: {}", Error::description(&what))
-        };
+    /// Parses and validates a config document. Malformed YAML, misspelled keys and values of the
+    /// wrong type are all reported as errors, with line numbers.
+    pub fn from_string(config_string: String) -> Result<Self, io::Error> {
+        Self::from_string_with_format(config_string, ConfigFormat::Yaml)
+    }
 
-        let mut config = Self::default();
-        if let Some(yaml) = yaml.get(0) {
-            if let Some(source) = yaml["source"].as_str() {
-                config.source_dir = source.to_string();
-            }
+    /// Like `from_string`, for a document in the given format.
+    pub fn from_string_with_format(config_string: String, format: ConfigFormat) -> Result<Self, io::Error> {
+        Self::from_yaml(&parse_config(&config_string, format, "config")?)
+    }
 
-            if let Some(output) = yaml["output"].as_str() {
-                config.output_dir = output.to_string();
-            }
+    /// Reads and merges several config files in order, with values in later files overriding
+    /// those in earlier ones. Nested mappings are merged key by key rather than replaced.
+    pub fn from_files<P: AsRef<Path>>(paths: &[P]) -> Result<Self, io::Error> {
+        Self::from_yaml(&load_merged(paths)?)
+    }
 
-            if let Some(config_port) = yaml["port"].as_str() {
-                config.port = config_port.to_string();
+    /// Like `from_files`, but follows each file with its `_config.<environment>.yml` overlay when
+    /// one exists, and exposes the environment to templates as `site.environment`.
+    pub fn from_files_for_environment<P: AsRef<Path>>(paths: &[P], environment: &str) -> Result<Self, io::Error> {
+        Self::from_yaml(&Self::load_yaml_for_environment(paths, environment)?)
+    }
+
+    /// Reads and merges config files and their environment overlays into a single document,
+    /// without interpreting any of the keys.
+    pub fn load_yaml_for_environment<P: AsRef<Path>>(paths: &[P], environment: &str) -> Result<Yaml, io::Error> {
+        Self::validate_environment(environment).map_err(|what| io::Error::new(io::ErrorKind::InvalidInput, what))?;
+
+        let mut layered = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            let overlay = Self::environment_config_file(path, environment);
+
+            layered.push(path.to_path_buf());
+            if overlay.is_file() {
+                layered.push(overlay);
             }
+        }
+
+        let mut yaml = load_merged(&layered)?;
+        if let Yaml::Hash(ref mut hash) = yaml {
+            hash.insert(Yaml::String("environment".to_string()), Yaml::String(environment.to_string()));
+        }
+
+        Ok(yaml)
+    }
+
+    /// Builds a config from an already merged document, checking the built-in keys as
+    /// `from_string` does. Line numbers aren't known at this point, so problems name the key.
+    pub fn from_yaml(yaml: &Yaml) -> Result<Self, io::Error> {
+        let problems = validate(yaml, &HashMap::new());
+        if !problems.is_empty() {
+            return Err(invalid_config("config", &problems));
+        }
+
+        let mut config = Config { site: yaml.clone(), ..Self::default() };
+
+        if let Some(source) = yaml["source"].as_str() {
+            config.source_dir = source.to_string();
+        }
+
+        if let Some(output) = yaml["output"].as_str() {
+            config.output_dir = output.to_string();
+        }
+
+        if let Some(host) = yaml["host"].as_str() {
+            config.host = host.to_string();
+        }
+
+        if let Some(port) = port_string(&yaml["port"]) {
+            config.port = Self::validate_port(&port).map_err(|what| io::Error::new(io::ErrorKind::InvalidData, what))?;
+        }
+
+        if let Some(log_format) = yaml["log_format"].as_str() {
+            config.log_format = log_format.to_string();
+        }
+
+        if let Some(base_url) = yaml["base_url"].as_str() {
+            config.base_url = base_url.trim_end_matches('/').to_string();
+        }
 
-            if let Some(markdown_options) = yaml["markdown_options"].as_vec() {
-                config.markdown_options = markdown_options.iter()
-                    .filter_map(|option| option.as_str().map(|option| option.to_owned()))
-                    .collect();
+        if let Some(base_path) = yaml["base_path"].as_str() {
+            config.base_path = Self::normalize_base_path(base_path);
+        }
+
+        if let Some(markdown_options) = yaml["markdown_options"].as_vec() {
+            config.markdown_options = markdown_options.iter()
+                .filter_map(|option| option.as_str().map(|option| option.to_owned()))
+                .collect();
+        }
+
+        if let Some(environment) = yaml["environment"].as_str() {
+            config.environment = environment.to_string();
+        }
+
+        if let Some(drafts) = yaml["drafts"].as_bool() {
+            config.drafts = drafts;
+        }
+
+        if let Some(future) = yaml["future"].as_bool() {
+            config.future = future;
+        }
+
+        if let Some(defaults) = yaml["defaults"].as_vec() {
+            config.defaults = defaults.iter().map(FrontMatterDefault::from_yaml).collect();
+        }
+
+        if let Some(excerpt_separator) = yaml["excerpt_separator"].as_str() {
+            config.excerpt_separator = excerpt_separator.to_string();
+        }
+
+        if let Some(git_dates) = yaml["git_dates"].as_bool() {
+            config.git_dates = git_dates;
+        }
+
+        if let Some(plugins) = yaml["plugins"].as_vec() {
+            config.plugins = plugins.iter()
+                .filter_map(|plugin| plugin.as_str().map(|plugin| plugin.to_owned()))
+                .collect();
+        }
+
+        if let Some(external_plugins) = yaml["external_plugins"].as_vec() {
+            config.external_plugins = external_plugins.iter().map(ExternalPluginConfig::from_yaml).collect();
+        }
+
+        Ok(config)
+    }
+
+    /// The effective configuration as YAML: every custom key from the config files, plus the
+    /// value actually in use for each built-in setting, defaults included.
+    pub fn to_yaml(&self) -> Yaml {
+        let mut hash = match self.site {
+            Yaml::Hash(ref hash) => hash.clone(),
+            _ => Default::default()
+        };
+
+        let settings = vec![
+            ("source", Yaml::String(self.source_dir.clone())),
+            ("output", Yaml::String(self.output_dir.clone())),
+            ("host", Yaml::String(self.host.clone())),
+            ("port", Yaml::Integer(i64::from(self.port))),
+            ("log_format", Yaml::String(self.log_format.clone())),
+            ("base_url", Yaml::String(self.base_url.clone())),
+            ("base_path", Yaml::String(self.base_path.clone())),
+            ("markdown_options", Yaml::Array(self.markdown_options.iter().cloned().map(Yaml::String).collect())),
+            ("environment", Yaml::String(self.environment.clone())),
+            ("drafts", Yaml::Boolean(self.drafts)),
+            ("future", Yaml::Boolean(self.future)),
+            ("excerpt_separator", Yaml::String(self.excerpt_separator.clone())),
+            ("git_dates", Yaml::Boolean(self.git_dates)),
+            ("plugins", Yaml::Array(self.plugins.iter().cloned().map(Yaml::String).collect())),
+            ("external_plugins", Yaml::Array(self.external_plugins.iter().map(ExternalPluginConfig::to_yaml).collect())),
+        ];
+
+        for (key, value) in settings {
+            hash.insert(Yaml::String(key.to_string()), value);
+        }
+
+        Yaml::Hash(hash)
+    }
+
+    /// The default front matter for a page at `path`, relative to the source directory. Values
+    /// from every matching entry in `defaults` are merged in order, so later entries win.
+    pub fn front_matter_defaults(&self, path: &Path, page_type: &str) -> Yaml {
+        let mut merged = Yaml::Hash(Default::default());
+
+        for default in self.defaults.iter().filter(|default| default.matches(path, page_type)) {
+            merge_yaml(&mut merged, default.values.clone());
+        }
+
+        merged
+    }
+
+    /// Renders `to_yaml` as a YAML document.
+    pub fn to_yaml_string(&self) -> String {
+        let mut output = String::new();
+        YamlEmitter::new(&mut output).dump(&self.to_yaml()).expect("Couldn't write config as YAML");
+
+        output
+    }
+
+    /// Splits a `key=value` override as passed to `--set`.
+    pub fn parse_override(assignment: &str) -> Result<(String, String), String> {
+        match assignment.find('=') {
+            Some(index) if !assignment[..index].trim().is_empty() => {
+                Ok((assignment[..index].trim().to_string(), assignment[index + 1..].to_string()))
+            },
+            _ => Err(format!("Invalid override \"{}\": expected key=value", assignment))
+        }
+    }
+
+    /// Picks out the `ALUMINUM_<KEY>` variables from an environment and turns them into overrides
+    /// for the config document `yaml`. Keys are lowercased and `__` separates nested keys, so
+    /// `ALUMINUM_SOCIAL__TWITTER` sets `social.twitter`. Only variables whose top-level key is a
+    /// built-in one or already in `yaml` are used, so unrelated variables that happen to share the
+    /// prefix are ignored. `ALUMINUM_ENV` selects the environment and is not an override.
+    pub fn environment_overrides<I: IntoIterator<Item = (String, String)>>(variables: I, yaml: &Yaml) -> Vec<(String, String)> {
+        let mut overrides: Vec<(String, String)> = variables.into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_OVERRIDE_PREFIX) && name != "ALUMINUM_ENV")
+            .map(|(name, value)| (name[ENV_OVERRIDE_PREFIX.len()..].to_lowercase().replace("__", "."), value))
+            .filter(|(key, _)| {
+                let top_level = key.split('.').next().unwrap_or("");
+                KNOWN_KEYS.iter().any(|&(known, _)| known == top_level) || !yaml[top_level].is_badvalue()
+            })
+            .collect();
+
+        overrides.sort();
+        overrides
+    }
+
+    /// Sets `key` in a config document, where dots in the key address nested mappings. The value
+    /// is read as a YAML scalar or flow collection, so `port=5000` sets a number and
+    /// `markdown_options=[tables]` a list; anything else is kept as a string.
+    pub fn apply_override(yaml: &mut Yaml, key: &str, value: &str) {
+        let mut target = yaml;
+
+        for part in key.split('.') {
+            if target.as_hash().is_none() {
+                *target = Yaml::Hash(Default::default());
             }
+
+            target = match *target {
+                Yaml::Hash(ref mut hash) => hash.entry(Yaml::String(part.to_string())).or_insert(Yaml::Null),
+                _ => unreachable!("Override target was just made a mapping")
+            };
         }
 
-        config
+        let is_flow_collection = value.starts_with('[') || value.starts_with('{');
+        *target = match YamlLoader::load_from_str(value).ok().and_then(|documents| documents.into_iter().next()) {
+            Some(Yaml::Array(_)) | Some(Yaml::Hash(_)) if !is_flow_collection => Yaml::String(value.to_string()),
+            Some(Yaml::BadValue) | None => Yaml::String(value.to_string()),
+            Some(parsed) => parsed
+        };
+    }
+
+    /// Looks for a config file in `start` and each of its parent directories in turn, returning
+    /// the first one found.
+    pub fn find_config_file(start: &Path) -> Option<PathBuf> {
+        start.ancestors().filter_map(Self::config_file_in).next()
+    }
+
+    /// The config file in `directory`, trying each of `CONFIG_FILE_NAMES` in order.
+    pub fn config_file_in(directory: &Path) -> Option<PathBuf> {
+        CONFIG_FILE_NAMES.iter()
+            .map(|file_name| directory.join(file_name))
+            .find(|config_file| config_file.is_file())
+    }
+
+    /// The overlay for `config_file` in the given environment, e.g. `_config.yml` becomes
+    /// `_config.production.yml`.
+    pub fn environment_config_file(config_file: &Path, environment: &str) -> PathBuf {
+        let stem = config_file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let file_name = match config_file.extension() {
+            Some(extension) => format!("{}.{}.{}", stem, environment, extension.to_string_lossy()),
+            None => format!("{}.{}", stem, environment)
+        };
+
+        config_file.with_file_name(file_name)
+    }
+
+    /// Checks an environment name is usable in a file name, e.g. `production` or `staging-eu`.
+    pub fn validate_environment(environment: &str) -> Result<(), String> {
+        let valid = !environment.is_empty() &&
+            environment.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("Invalid environment \"{}\": expected letters, numbers, '-' or '_'", environment))
+        }
+    }
+
+    /// Normalizes a base path to have a leading slash and no trailing slash, so `docs/` becomes
+    /// `/docs` and `/` becomes an empty string.
+    pub fn normalize_base_path(base_path: &str) -> String {
+        let base_path = base_path.trim().trim_matches('/');
+
+        if base_path.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", base_path)
+        }
+    }
+
+    /// Parses a port number, rejecting anything outside of 1-65535.
+    pub fn validate_port(port: &str) -> Result<u16, String> {
+        match port.trim().parse::<u16>() {
+            Ok(0) | Err(_) => Err(format!("Invalid port \"{}\": expected a number between 1 and 65535", port)),
+            Ok(port) => Ok(port)
+        }
     }
 }
 
@@ -45,16 +509,356 @@ impl Default for Config {
         Config {
             source_dir: "pages".to_string(),
             output_dir: "_site".to_string(),
-            port: "4000".to_string(),
-            markdown_options: Vec::new()
+            host: "127.0.0.1".to_string(),
+            port: 4000,
+            log_format: "{method} {path} {status} {bytes}B {duration}ms".to_string(),
+            base_url: String::new(),
+            base_path: String::new(),
+            markdown_options: Vec::new(),
+            environment: DEFAULT_ENVIRONMENT.to_string(),
+            drafts: false,
+            future: false,
+            defaults: Vec::new(),
+            excerpt_separator: DEFAULT_EXCERPT_SEPARATOR.to_string(),
+            git_dates: false,
+            plugins: Vec::new(),
+            external_plugins: Vec::new(),
+            site: Yaml::Hash(Default::default()),
+            root_dir: ".".to_string()
         }
     }
 }
 
+/// Reads each config file in turn and merges them into a single document.
+fn load_merged<P: AsRef<Path>>(paths: &[P]) -> Result<Yaml, io::Error> {
+    let mut merged = Yaml::Hash(Default::default());
+
+    for path in paths {
+        let path = path.as_ref();
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|what| io::Error::new(what.kind(), format!("Couldn't read config file {}: {}", path.display(), what)))?;
+
+        let yaml = parse_config(&contents, ConfigFormat::from_path(path), &format!("config file {}", path.display()))?;
+        merge_yaml(&mut merged, yaml);
+    }
+
+    Ok(merged)
+}
+
+/// Parses a single config document and validates it, using the source to report the line each
+/// problem is on. `origin` names the document in error messages.
+fn parse_config(source: &str, format: ConfigFormat, origin: &str) -> Result<Yaml, io::Error> {
+    let parse_error = |what: String| io::Error::new(io::ErrorKind::InvalidData, format!("Couldn't parse {}: {}", origin, what));
+
+    let (yaml, lines) = match format {
+        ConfigFormat::Yaml => {
+            let yaml = YamlLoader::load_from_str(source)
+                .map_err(|what| parse_error(what.to_string()))?
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| Yaml::Hash(Default::default()));
+
+            let mut key_lines = KeyLines::default();
+            Parser::new(source.chars()).load(&mut key_lines, false).map_err(|what| parse_error(what.to_string()))?;
+
+            (yaml, key_lines.lines)
+        },
+        ConfigFormat::Toml => {
+            let toml = source.parse::<toml::Value>().map_err(|what| parse_error(what.to_string()))?;
+
+            (toml_to_yaml(toml), toml_key_lines(source))
+        }
+    };
+
+    let problems = validate(&yaml, &lines);
+    if problems.is_empty() {
+        Ok(yaml)
+    } else {
+        Err(invalid_config(origin, &problems))
+    }
+}
+
+/// Converts a parsed TOML document to the YAML representation the rest of the config uses.
+/// Dates have no YAML equivalent here, so they're kept as strings.
+fn toml_to_yaml(toml: toml::Value) -> Yaml {
+    match toml {
+        toml::Value::String(string) => Yaml::String(string),
+        toml::Value::Integer(integer) => Yaml::Integer(integer),
+        toml::Value::Float(float) => Yaml::Real(float.to_string()),
+        toml::Value::Boolean(boolean) => Yaml::Boolean(boolean),
+        toml::Value::Datetime(datetime) => Yaml::String(datetime.to_string()),
+        toml::Value::Array(array) => Yaml::Array(array.into_iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(table) => Yaml::Hash(table.into_iter()
+            .map(|(key, value)| (Yaml::String(key), toml_to_yaml(value)))
+            .collect())
+    }
+}
+
+/// Finds the line each top-level key of a TOML document is on. Keys only count until the first
+/// `[table]` header, after which they belong to that table.
+fn toml_key_lines(source: &str) -> HashMap<String, usize> {
+    let mut lines = HashMap::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.trim();
+
+        if line.starts_with('[') {
+            break;
+        }
+
+        if let Some(index) = line.find('=') {
+            let key = line[..index].trim().trim_matches('"');
+            if !key.is_empty() && !key.starts_with('#') {
+                lines.entry(key.to_string()).or_insert(number + 1);
+            }
+        }
+    }
+
+    lines
+}
+
+fn invalid_config(origin: &str, problems: &[String]) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {}:\n  {}", origin, problems.join("\n  ")))
+}
+
+/// Checks the built-in keys of a config document hold the right type of value, and that no other
+/// key looks like a misspelling of one. Problems are prefixed with the line they're on when
+/// `lines` knows it.
+fn validate(yaml: &Yaml, lines: &HashMap<String, usize>) -> Vec<String> {
+    let hash = match *yaml {
+        Yaml::Hash(ref hash) => hash,
+        Yaml::Null => return Vec::new(),
+        ref other => return vec![format!("expected a mapping of settings, found {}", describe_yaml(other))]
+    };
+
+    let mut problems = Vec::new();
+
+    for (key, value) in hash {
+        let key = match key.as_str() {
+            Some(key) => key,
+            None => continue
+        };
+
+        let location = match lines.get(key) {
+            Some(line) => format!("line {}: ", line),
+            None => String::new()
+        };
+
+        match KNOWN_KEYS.iter().find(|&&(known, _)| known == key) {
+            Some(&(_, value_type)) => {
+                if let Err(what) = value_type.check(value) {
+                    problems.push(format!("{}`{}` {}", location, key, what));
+                }
+            },
+            None => {
+                if let Some(suggestion) = suggest_key(key) {
+                    problems.push(format!("{}unknown key `{}`, did you mean `{}`?", location, key, suggestion));
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+/// The built-in key `key` was most likely meant to be, if it's close enough to one to be a typo
+/// rather than a custom site key.
+fn suggest_key(key: &str) -> Option<&'static str> {
+    KNOWN_KEYS.iter()
+        .map(|&(known, _)| known)
+        .filter(|known| levenshtein(key, known) <= 2 && jaro_winkler(key, known) >= 0.9)
+        .max_by(|a, b| jaro_winkler(key, a).partial_cmp(&jaro_winkler(key, b)).expect("Similarity is never NaN"))
+}
+
+fn port_string(yaml: &Yaml) -> Option<String> {
+    match *yaml {
+        Yaml::Integer(port) => Some(port.to_string()),
+        Yaml::String(ref port) => Some(port.clone()),
+        _ => None
+    }
+}
+
+fn describe_yaml(yaml: &Yaml) -> &'static str {
+    match *yaml {
+        Yaml::Integer(_) => "a number",
+        Yaml::Real(_) => "a decimal number",
+        Yaml::String(_) => "a string",
+        Yaml::Boolean(_) => "true/false",
+        Yaml::Array(_) => "a list",
+        Yaml::Hash(_) => "a mapping",
+        Yaml::Alias(_) => "an alias",
+        Yaml::Null | Yaml::BadValue => "nothing"
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ValueType {
+    String,
+    Bool,
+    Port,
+    StringList,
+    Defaults,
+    ExternalPlugins
+}
+
+impl ValueType {
+    /// Checks `value` has this type. An empty value is always allowed and leaves the default.
+    fn check(self, value: &Yaml) -> Result<(), String> {
+        match (self, value) {
+            (_, &Yaml::Null) |
+            (ValueType::String, &Yaml::String(_)) |
+            (ValueType::Bool, &Yaml::Boolean(_)) => Ok(()),
+            (ValueType::Port, &Yaml::Integer(_)) |
+            (ValueType::Port, &Yaml::String(_)) => {
+                let port = port_string(value).expect("Port is a number or string");
+                Config::validate_port(&port).map(|_| ()).map_err(|what| format!("is invalid: {}", what))
+            },
+            (ValueType::StringList, Yaml::Array(items)) if items.iter().all(|item| item.as_str().is_some()) => Ok(()),
+            (ValueType::Defaults, Yaml::Array(entries)) => entries.iter().enumerate().try_for_each(|(index, entry)| {
+                check_default(entry).map_err(|what| format!("entry {} {}", index + 1, what))
+            }),
+            (ValueType::ExternalPlugins, Yaml::Array(entries)) => entries.iter().enumerate().try_for_each(|(index, entry)| {
+                check_external_plugin(entry).map_err(|what| format!("entry {} {}", index + 1, what))
+            }),
+            (value_type, value) => Err(format!("should be {}, found {}", value_type.describe(), describe_yaml(value)))
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            ValueType::String => "a string",
+            ValueType::Bool => "true or false",
+            ValueType::Port => "a port number",
+            ValueType::StringList => "a list of strings",
+            ValueType::Defaults => "a list of scopes and values",
+            ValueType::ExternalPlugins => "a list of plugin commands"
+        }
+    }
+}
+
+/// Checks a single entry in `defaults` has a mapping of `values`, and a `scope` whose `path` and
+/// `type` are strings and whose `path` is a valid glob.
+fn check_default(entry: &Yaml) -> Result<(), String> {
+    if entry["values"].as_hash().is_none() {
+        return Err(format!("should have a mapping of `values`, found {}", describe_yaml(&entry["values"])));
+    }
+
+    match entry["scope"] {
+        Yaml::Hash(_) | Yaml::BadValue => {},
+        ref other => return Err(format!("`scope` should be a mapping, found {}", describe_yaml(other)))
+    }
+
+    for key in &["path", "type"] {
+        match entry["scope"][*key] {
+            Yaml::String(_) | Yaml::BadValue => {},
+            ref other => return Err(format!("`scope.{}` should be a string, found {}", key, describe_yaml(other)))
+        }
+    }
+
+    if let Some(path) = entry["scope"]["path"].as_str() {
+        Pattern::new(path).map_err(|what| format!("`scope.path` \"{}\" is not a valid pattern: {}", path, what.msg))?;
+    }
+
+    Ok(())
+}
+
+/// Checks a single entry in `external_plugins` has a `name` and `command`, only lists hooks
+/// plugins can run at, and has a positive `timeout` if any.
+fn check_external_plugin(entry: &Yaml) -> Result<(), String> {
+    for key in &["name", "command"] {
+        match entry[*key] {
+            Yaml::String(ref value) if !value.trim().is_empty() => {},
+            ref other => return Err(format!("should have a `{}`, found {}", key, describe_yaml(other)))
+        }
+    }
+
+    let hooks = match entry["hooks"].as_vec() {
+        Some(hooks) if !hooks.is_empty() => hooks,
+        _ => return Err(format!("should list its `hooks`, some of {}", HOOKS.join(", ")))
+    };
+
+    for hook in hooks {
+        match hook.as_str() {
+            Some(hook) if HOOKS.contains(&hook) => {},
+            Some(hook) => return Err(format!("has an unknown hook `{}`; the hooks are {}", hook, HOOKS.join(", "))),
+            None => return Err(format!("`hooks` should be strings, found {}", describe_yaml(hook)))
+        }
+    }
+
+    match entry["timeout"] {
+        Yaml::Integer(timeout) if timeout > 0 => Ok(()),
+        Yaml::BadValue => Ok(()),
+        ref other => Err(format!("`timeout` should be a positive number of seconds, found {}", describe_yaml(other)))
+    }
+}
+
+/// Records the line each top-level key of a YAML document is on, so validation errors can point
+/// at it. Nested keys aren't tracked.
+#[derive(Default)]
+struct KeyLines {
+    lines: HashMap<String, usize>,
+    /// For each open collection, whether it's a mapping and, if so, whether a key comes next.
+    open: Vec<(bool, bool)>
+}
+
+impl KeyLines {
+    fn end_node(&mut self) {
+        if let Some(&mut (true, ref mut expecting_key)) = self.open.last_mut() {
+            *expecting_key = !*expecting_key;
+        }
+    }
+}
+
+impl MarkedEventReceiver for KeyLines {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::MappingStart(_) => self.open.push((true, true)),
+            Event::SequenceStart(_) => self.open.push((false, false)),
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.open.pop();
+                self.end_node();
+            },
+            Event::Scalar(key, ..) => {
+                if self.open.len() == 1 && self.open[0] == (true, true) {
+                    self.lines.entry(key).or_insert_with(|| mark.line());
+                }
+                self.end_node();
+            },
+            Event::Alias(_) => self.end_node(),
+            _ => {}
+        }
+    }
+}
+
+/// Merges `overlay` onto `base`. Mappings are merged recursively; any other value in `overlay`
+/// replaces the one in `base`.
+pub(crate) fn merge_yaml(base: &mut Yaml, overlay: Yaml) {
+    match (base, overlay) {
+        (&mut Yaml::Hash(ref mut base), Yaml::Hash(overlay)) => {
+            for (key, value) in overlay {
+                if let Some(existing) = base.get_mut(&key) {
+                    merge_yaml(existing, value);
+                    continue;
+                }
+
+                base.insert(key, value);
+            }
+        },
+        (base, overlay) => *base = overlay
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    use tempdir::TempDir;
+
     fn good_setup() -> String {
         "source: pages\n\
          output: _site\n\
@@ -75,14 +879,15 @@ mod tests {
 
         assert_eq!("pages", config.source_dir);
         assert_eq!("_site", config.output_dir);
-        assert_eq!("4000", config.port);
+        assert_eq!("127.0.0.1", config.host);
+        assert_eq!(4000, config.port);
     }
 
     #[test]
     fn it_parses_input_directory_option_in_config() {
         let config_string = good_setup();
 
-        let config = Config::from_string(config_string);
+        let config = Config::from_string(config_string).expect("Parse config");
 
         assert_eq!("pages", config.source_dir);
     }
@@ -91,7 +896,7 @@ mod tests {
     fn it_parses_output_directory_option_in_config() {
         let config_string = good_setup();
 
-        let config = Config::from_string(config_string);
+        let config = Config::from_string(config_string).expect("Parse config");
 
         assert_eq!("_site", config.output_dir);
     }
@@ -100,9 +905,121 @@ mod tests {
     fn it_parses_port_number_in_config() {
         let config_string = good_setup();
 
-        let config = Config::from_string(config_string);
+        let config = Config::from_string(config_string).expect("Parse config");
 
-        assert_eq!("4000", config.port);
+        assert_eq!(4000, config.port);
+    }
+
+    #[test]
+    fn it_parses_integer_and_string_ports() {
+        assert_eq!(4001, Config::from_string("port: 4001".to_string()).expect("Parse config").port);
+        assert_eq!(4002, Config::from_string("port: \"4002\"".to_string()).expect("Parse config").port);
+    }
+
+    #[test]
+    fn it_parses_the_host_in_config() {
+        let config = Config::from_string("host: 0.0.0.0".to_string()).expect("Parse config");
+
+        assert_eq!("0.0.0.0", config.host);
+    }
+
+    #[test]
+    fn it_rejects_out_of_range_ports() {
+        assert!(Config::validate_port("0").is_err());
+        assert!(Config::validate_port("65536").is_err());
+        assert!(Config::validate_port("http").is_err());
+        assert_eq!(Ok(8080), Config::validate_port("8080"));
+    }
+
+    #[test]
+    fn it_parses_and_normalizes_the_base_url_and_path() {
+        let config = Config::from_string("base_url: https://example.com/\nbase_path: docs/".to_string()).expect("Parse config");
+
+        assert_eq!("https://example.com", config.base_url);
+        assert_eq!("/docs", config.base_path);
+        assert_eq!("", Config::normalize_base_path("/"));
+    }
+
+    #[test]
+    fn it_rejects_an_invalid_port() {
+        let error = Config::from_string("port: 70000".to_string()).err().expect("Invalid port should fail");
+
+        assert!(error.to_string().contains("line 1: `port` is invalid"));
+    }
+
+    #[test]
+    fn it_parses_draft_and_future_switches() {
+        let config = Config::from_string("drafts: true\nfuture: false".to_string()).expect("Parse config");
+
+        assert!(config.drafts);
+        assert!(!config.future);
+        assert!(Config::from_string("drafts: sometimes".to_string()).is_err());
+    }
+
+    #[test]
+    fn it_applies_front_matter_defaults_by_path_and_type() {
+        let config = Config::from_string("defaults:\n\
+            - values:\n    author: platform-team\n    layout: page\n\
+            - scope:\n    path: docs\n  values:\n    layout: doc\n\
+            - scope:\n    path: \"**/*.md\"\n    type: posts\n  values:\n    comments: true".to_string())
+            .expect("Parse config");
+
+        let docs = config.front_matter_defaults(Path::new("docs/guide/intro.md"), "docs");
+        assert_eq!(Some("platform-team"), docs["author"].as_str());
+        assert_eq!(Some("doc"), docs["layout"].as_str());
+        assert_eq!(None, docs["comments"].as_bool());
+
+        let post = config.front_matter_defaults(Path::new("posts/hello.md"), "posts");
+        assert_eq!(Some("page"), post["layout"].as_str());
+        assert_eq!(Some(true), post["comments"].as_bool());
+
+        assert_eq!(Some("page"), config.front_matter_defaults(Path::new("documents.md"), "pages")["layout"].as_str());
+    }
+
+    #[test]
+    fn it_matches_glob_scopes_one_directory_at_a_time() {
+        let default = FrontMatterDefault { path: "docs/*.md".to_string(), page_type: None, values: Yaml::Null };
+
+        assert!(default.matches(Path::new("docs/intro.md"), "docs"));
+        assert!(!default.matches(Path::new("docs/guide/intro.md"), "docs"));
+    }
+
+    #[test]
+    fn it_rejects_malformed_defaults() {
+        let error = Config::from_string("defaults:\n  - scope:\n      path: docs".to_string()).err().expect("Missing values should fail");
+
+        assert!(error.to_string().contains("line 1: `defaults` entry 1 should have a mapping of `values`"));
+        assert!(Config::from_string("defaults:\n  - scope:\n      path: \"[docs\"\n    values: {}".to_string()).is_err());
+    }
+
+    #[test]
+    fn it_reads_the_excerpt_separator() {
+        assert_eq!("<!--more-->", Config::default().excerpt_separator);
+
+        let config = Config::from_string("excerpt_separator: \"<!-- excerpt -->\"".to_string()).expect("Parse config");
+        assert_eq!("<!-- excerpt -->", config.excerpt_separator);
+        assert!(Config::from_string("excerpt_separator: [more]".to_string()).is_err());
+    }
+
+    #[test]
+    fn it_reads_external_plugins() {
+        let config = Config::from_string("external_plugins:\n  - name: tags\n    command: node tags.js\n    hooks: [generate]".to_string())
+            .expect("Parse config");
+
+        assert_eq!(vec![ExternalPluginConfig {
+            name: "tags".to_string(),
+            command: "node tags.js".to_string(),
+            hooks: vec!["generate".to_string()],
+            timeout: DEFAULT_TIMEOUT
+        }], config.external_plugins);
+
+        let error = Config::from_string("external_plugins:\n  - name: tags\n    command: node tags.js\n    hooks: [render]".to_string())
+            .err().expect("Unknown hook");
+        assert!(error.to_string().contains("`external_plugins` entry 1 has an unknown hook `render`"));
+
+        let error = Config::from_string("external_plugins:\n  - name: tags\n    hooks: [generate]\n    timeout: 0".to_string())
+            .err().expect("Missing command");
+        assert!(error.to_string().contains("`external_plugins` entry 1 should have a `command`, found nothing"));
     }
 
     #[test]
@@ -110,16 +1027,249 @@ mod tests {
         let config_string = good_setup();
         let expected_options = vec!["tables", "footnotes"];
 
-        let config = Config::from_string(config_string);
+        let config = Config::from_string(config_string).expect("Parse config");
 
         assert_eq!(expected_options, config.markdown_options);
     }
 
     #[test]
-    #[should_panic]
-    fn it_panics_on_poorly_formed_file() {
+    fn it_reports_poorly_formed_files() {
         let config_string = bad_setup();
 
-        Config::from_string(config_string);
+        let error = Config::from_string(config_string).err().expect("Malformed YAML should fail");
+
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert!(error.to_string().contains("at line 1"));
+    }
+
+    #[test]
+    fn it_suggests_known_keys_for_misspellings() {
+        let error = Config::from_string("source: pages\nouput: public".to_string()).err().expect("Misspelled key should fail");
+
+        assert!(error.to_string().contains("line 2: unknown key `ouput`, did you mean `output`?"));
+    }
+
+    #[test]
+    fn it_keeps_unrelated_keys_as_custom_site_data() {
+        let config = Config::from_string("title: My Site\npost: hello\nbase: x".to_string()).expect("Parse config");
+
+        assert_eq!(Some("My Site"), config.site["title"].as_str());
+        assert_eq!(Some("hello"), config.site["post"].as_str());
+    }
+
+    #[test]
+    fn it_reports_type_mismatches_with_line_numbers() {
+        let error = Config::from_string("source: pages\n\noutput:\n  - public\nmarkdown_options: tables".to_string())
+            .err()
+            .expect("Wrong types should fail");
+        let message = error.to_string();
+
+        assert!(message.contains("line 3: `output` should be a string, found a list"));
+        assert!(message.contains("line 5: `markdown_options` should be a list of strings, found a string"));
+    }
+
+    #[test]
+    fn it_reports_type_mismatches_from_overrides_by_key() {
+        let mut yaml = YamlLoader::load_from_str("source: pages").unwrap().remove(0);
+        Config::apply_override(&mut yaml, "port", "true");
+
+        let error = Config::from_yaml(&yaml).err().expect("Boolean port should fail");
+
+        assert!(error.to_string().contains("`port` should be a port number, found true/false"));
+    }
+
+    fn write_file(path: &Path, contents: &str) {
+        File::create(path).expect("Create file").write_all(contents.as_bytes()).expect("Write file");
+    }
+
+    #[test]
+    fn it_finds_the_config_file_in_a_parent_directory() {
+        let temp_dir = TempDir::new("find-config").expect("Temp Dir");
+        let nested_dir = temp_dir.path().join("pages/posts");
+        fs::create_dir_all(&nested_dir).expect("Create nested directory");
+        write_file(&temp_dir.path().join(CONFIG_FILE_NAME), "output: _site");
+
+        assert_eq!(Some(temp_dir.path().join(CONFIG_FILE_NAME)), Config::find_config_file(&nested_dir));
+    }
+
+    #[test]
+    fn it_does_not_find_a_config_file_that_is_not_there() {
+        let temp_dir = TempDir::new("find-no-config").expect("Temp Dir");
+
+        assert_eq!(None, Config::find_config_file(&temp_dir.path().join("missing")));
+    }
+
+    #[test]
+    fn it_merges_config_files_with_later_files_taking_precedence() {
+        let temp_dir = TempDir::new("merge-config").expect("Temp Dir");
+        let base = temp_dir.path().join("base.yml");
+        let overlay = temp_dir.path().join("overlay.yml");
+        write_file(&base, "source: pages\noutput: _site\nport: 4000");
+        write_file(&overlay, "output: public\nport: 5000");
+
+        let config = Config::from_files(&[&base, &overlay]).expect("Merge config files");
+
+        assert_eq!("pages", config.source_dir);
+        assert_eq!("public", config.output_dir);
+        assert_eq!(5000, config.port);
+    }
+
+    #[test]
+    fn it_merges_nested_mappings_key_by_key() {
+        let mut base = YamlLoader::load_from_str("outer:\n  kept: 1\n  replaced: 2").unwrap().remove(0);
+        let overlay = YamlLoader::load_from_str("outer:\n  replaced: 3\n  added: 4").unwrap().remove(0);
+
+        merge_yaml(&mut base, overlay);
+
+        assert_eq!(Some(1), base["outer"]["kept"].as_i64());
+        assert_eq!(Some(3), base["outer"]["replaced"].as_i64());
+        assert_eq!(Some(4), base["outer"]["added"].as_i64());
+    }
+
+    #[test]
+    fn it_reports_missing_config_files() {
+        let error = Config::from_files(&["does/not/exist.yml"]).err().expect("Missing file should fail");
+
+        assert_eq!(io::ErrorKind::NotFound, error.kind());
+        assert!(error.to_string().contains("does/not/exist.yml"));
+    }
+
+    #[test]
+    fn it_names_environment_overlays_after_the_config_file() {
+        assert_eq!(PathBuf::from("site/_config.production.yml"), Config::environment_config_file(Path::new("site/_config.yml"), "production"));
+        assert_eq!(PathBuf::from("custom.staging"), Config::environment_config_file(Path::new("custom"), "staging"));
+    }
+
+    #[test]
+    fn it_overlays_the_environment_config_file() {
+        let temp_dir = TempDir::new("environment-config").expect("Temp Dir");
+        let config_file = temp_dir.path().join(CONFIG_FILE_NAME);
+        write_file(&config_file, "base_url: http://localhost:4000
+output: _site");
+        write_file(&temp_dir.path().join("_config.production.yml"), "base_url: https://example.com");
+
+        let production = Config::from_files_for_environment(&[&config_file], "production").expect("Production config");
+        let development = Config::from_files_for_environment(&[&config_file], DEFAULT_ENVIRONMENT).expect("Development config");
+
+        assert_eq!("https://example.com", production.base_url);
+        assert_eq!("_site", production.output_dir);
+        assert_eq!("production", production.environment);
+        assert_eq!(Some("production"), production.site["environment"].as_str());
+        assert_eq!("http://localhost:4000", development.base_url);
+        assert_eq!(Some("development"), development.site["environment"].as_str());
+    }
+
+    #[test]
+    fn it_rejects_environment_names_that_are_not_file_safe() {
+        assert!(Config::validate_environment("production").is_ok());
+        assert!(Config::validate_environment("").is_err());
+        assert!(Config::validate_environment("../secrets").is_err());
+    }
+
+    #[test]
+    fn it_parses_set_overrides() {
+        assert_eq!(Ok(("output".to_string(), "public".to_string())), Config::parse_override("output=public"));
+        assert_eq!(Ok(("title".to_string(), "a=b".to_string())), Config::parse_override("title=a=b"));
+        assert!(Config::parse_override("output").is_err());
+        assert!(Config::parse_override("=public").is_err());
+    }
+
+    #[test]
+    fn it_reads_overrides_from_prefixed_environment_variables() {
+        let variables = vec![
+            ("ALUMINUM_OUTPUT".to_string(), "public".to_string()),
+            ("ALUMINUM_SOCIAL__TWITTER".to_string(), "@aluminum".to_string()),
+            ("ALUMINUM_ENV".to_string(), "production".to_string()),
+            ("ALUMINUM_PORTS".to_string(), "8000-9000".to_string()),
+            ("ALUMINUM_".to_string(), "empty".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ];
+        let yaml = YamlLoader::load_from_str("social:\n  github: ELD").unwrap().remove(0);
+
+        let expected = vec![
+            ("output".to_string(), "public".to_string()),
+            ("social.twitter".to_string(), "@aluminum".to_string()),
+        ];
+
+        assert_eq!(expected, Config::environment_overrides(variables, &yaml));
+    }
+
+    #[test]
+    fn it_applies_overrides_with_yaml_types_and_nested_keys() {
+        let mut yaml = YamlLoader::load_from_str("output: _site\nsocial: none").unwrap().remove(0);
+
+        Config::apply_override(&mut yaml, "output", "public");
+        Config::apply_override(&mut yaml, "port", "5000");
+        Config::apply_override(&mut yaml, "markdown_options", "[tables, footnotes]");
+        Config::apply_override(&mut yaml, "social.twitter", "@aluminum");
+        Config::apply_override(&mut yaml, "title", "Notes: a blog");
+
+        let config = Config::from_yaml(&yaml).expect("Config from YAML");
+
+        assert_eq!("public", config.output_dir);
+        assert_eq!(5000, config.port);
+        assert_eq!(vec!["tables", "footnotes"], config.markdown_options);
+        assert_eq!(Some("@aluminum"), yaml["social"]["twitter"].as_str());
+        assert_eq!(Some("Notes: a blog"), yaml["title"].as_str());
+    }
+
+    #[test]
+    fn it_includes_defaults_and_custom_keys_in_the_effective_config() {
+        let config = Config::from_string("title: My Site\noutput: public".to_string()).expect("Parse config");
+        let yaml = config.to_yaml();
+
+        assert_eq!(Some("My Site"), yaml["title"].as_str());
+        assert_eq!(Some("public"), yaml["output"].as_str());
+        assert_eq!(Some(4000), yaml["port"].as_i64());
+        assert_eq!(Some(DEFAULT_ENVIRONMENT), yaml["environment"].as_str());
+        assert!(config.to_yaml_string().contains("title: My Site"));
+    }
+
+    #[test]
+    fn it_reads_toml_config_into_the_same_config() {
+        let config_string = "source = \"pages\"\noutput = \"public\"\nport = 4005\nmarkdown_options = [\"tables\"]\n\n[social]\ntwitter = \"@aluminum\"";
+
+        let config = Config::from_string_with_format(config_string.to_string(), ConfigFormat::Toml).expect("Parse TOML config");
+
+        assert_eq!("pages", config.source_dir);
+        assert_eq!("public", config.output_dir);
+        assert_eq!(4005, config.port);
+        assert_eq!(vec!["tables"], config.markdown_options);
+        assert_eq!(Some("@aluminum"), config.site["social"]["twitter"].as_str());
+    }
+
+    #[test]
+    fn it_reports_toml_problems_with_line_numbers() {
+        let config_string = "source = \"pages\"\nouput = \"public\"\n\n[social]\nouput = \"fine\"";
+
+        let error = Config::from_string_with_format(config_string.to_string(), ConfigFormat::Toml).err().expect("Misspelled key should fail");
+
+        assert!(error.to_string().contains("line 2: unknown key `ouput`, did you mean `output`?"));
+    }
+
+    #[test]
+    fn it_finds_toml_config_files_and_prefers_yaml() {
+        let temp_dir = TempDir::new("find-toml-config").expect("Temp Dir");
+        write_file(&temp_dir.path().join("aluminum.toml"), "output = \"public\"");
+
+        assert_eq!(Some(temp_dir.path().join("aluminum.toml")), Config::find_config_file(temp_dir.path()));
+        assert_eq!(ConfigFormat::Toml, ConfigFormat::from_path(&temp_dir.path().join("aluminum.toml")));
+
+        write_file(&temp_dir.path().join(CONFIG_FILE_NAME), "output: _site");
+
+        assert_eq!(Some(temp_dir.path().join(CONFIG_FILE_NAME)), Config::find_config_file(temp_dir.path()));
+    }
+
+    #[test]
+    fn it_merges_toml_and_yaml_config_files() {
+        let temp_dir = TempDir::new("merge-toml-config").expect("Temp Dir");
+        let base = temp_dir.path().join("_config.toml");
+        write_file(&base, "output = \"_site\"\nport = 4000");
+        write_file(&temp_dir.path().join("_config.production.toml"), "output = \"public\"");
+
+        let config = Config::from_files_for_environment(&[&base], "production").expect("Merge TOML config");
+
+        assert_eq!("public", config.output_dir);
+        assert_eq!(4000, config.port);
     }
 }
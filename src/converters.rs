@@ -0,0 +1,97 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use pulldown_cmark::{html, Parser};
+
+use super::generation::Page;
+
+/// Turns a page's contents into its output, after Liquid has run, e.g. Markdown into HTML.
+/// Converters are picked by the source file's extension; files without one are copied as assets.
+pub trait Converter: Send + Sync {
+    fn convert(&self, contents: &str, page: &Page) -> Result<String, io::Error>;
+
+    /// The extension of the file the page is written to.
+    fn output_extension(&self) -> &str {
+        "html"
+    }
+
+    /// Whether files are only pages when they start with front matter. Those without any are
+    /// copied as assets.
+    fn requires_front_matter(&self) -> bool {
+        false
+    }
+}
+
+/// Markdown, with the config's `markdown_options`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Markdown;
+
+impl Converter for Markdown {
+    fn convert(&self, contents: &str, page: &Page) -> Result<String, io::Error> {
+        let mut parsed_html = String::with_capacity(contents.len() * 3 / 2);
+        html::push_html(&mut parsed_html, Parser::new_ext(contents, page.markdown_options()));
+
+        Ok(parsed_html)
+    }
+}
+
+/// Text that's written as it is once Liquid has run, to a `.txt` file. As in Jekyll, only text
+/// files with front matter are pages.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PlainText;
+
+impl Converter for PlainText {
+    fn convert(&self, contents: &str, _page: &Page) -> Result<String, io::Error> {
+        Ok(contents.to_string())
+    }
+
+    fn output_extension(&self) -> &str {
+        "txt"
+    }
+
+    fn requires_front_matter(&self) -> bool {
+        true
+    }
+}
+
+/// The converter for a file extension that's supported out of the box.
+pub fn built_in_converter(extension: &str) -> Option<Arc<dyn Converter>> {
+    match extension {
+        "md" | "markdown" | "mdown" => Some(Arc::new(Markdown)),
+        "txt" => Some(Arc::new(PlainText)),
+        _ => None
+    }
+}
+
+/// The extension converters are picked by, without the dot.
+pub fn source_extension(path: &Path) -> &str {
+    path.extension().and_then(|extension| extension.to_str()).unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::generation::PageGenerator;
+
+    #[test]
+    fn it_has_converters_for_markdown_and_text() {
+        let page = PageGenerator::new().parse_source("notes.markdown", "").expect("Parse page");
+
+        for extension in &["md", "markdown", "mdown"] {
+            let converter = built_in_converter(extension).expect("Markdown converter");
+            assert_eq!("html", converter.output_extension());
+            assert_eq!("<p><em>Hi</em></p>\n", converter.convert("*Hi*", &page).expect("Convert"));
+            assert!(!converter.requires_front_matter());
+        }
+
+        let text = built_in_converter("txt").expect("Text converter");
+        assert_eq!("txt", text.output_extension());
+        assert_eq!("*Hi*", text.convert("*Hi*", &page).expect("Convert"));
+        assert!(text.requires_front_matter());
+
+        assert!(built_in_converter("html").is_none());
+        assert_eq!("markdown", source_extension(Path::new("blog/notes.markdown")));
+        assert_eq!("", source_extension(Path::new("LICENSE")));
+    }
+}
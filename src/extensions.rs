@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use liquid::{Context, Error, FilterError, LiquidOptions, Renderable, Token, Value};
+use liquid::lexer::Element;
+
+use super::converters::{built_in_converter, Converter};
+
+/// A custom Liquid filter: takes the input value and the filter's arguments.
+pub type CustomFilter = dyn Fn(&Value, &[Value]) -> Result<Value, FilterError> + Send + Sync;
+
+/// A custom Liquid tag like `{% name arguments %}`: takes the tag's name and arguments.
+pub type CustomTag = dyn Fn(&str, &[Token], &LiquidOptions) -> Result<Box<dyn Renderable>, Error> + Send + Sync;
+
+/// A custom Liquid block like `{% name arguments %}...{% endname %}`: takes the block's name,
+/// arguments and the elements inside it, which `liquid::parser::parse` turns into renderables.
+pub type CustomBlock = dyn Fn(&str, &[Token], &[Element], &LiquidOptions) -> Result<Box<dyn Renderable>, Error> + Send + Sync;
+
+/// Custom Liquid filters, tags and blocks, and content converters, available to every page a
+/// `PageGenerator` renders. They're registered after the built-in ones, so they can replace them.
+#[derive(Clone, Default)]
+pub struct TemplateExtensions {
+    filters: Vec<(String, Arc<CustomFilter>)>,
+    tags: Vec<(String, Arc<CustomTag>)>,
+    blocks: Vec<(String, Arc<CustomBlock>)>,
+    converters: Vec<(String, Arc<dyn Converter>)>,
+}
+
+impl TemplateExtensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_filter<F>(&mut self, name: &str, filter: F) -> &mut Self
+        where F: Fn(&Value, &[Value]) -> Result<Value, FilterError> + Send + Sync + 'static
+    {
+        self.filters.push((name.to_string(), Arc::new(filter)));
+        self
+    }
+
+    pub fn add_tag<F>(&mut self, name: &str, tag: F) -> &mut Self
+        where F: Fn(&str, &[Token], &LiquidOptions) -> Result<Box<dyn Renderable>, Error> + Send + Sync + 'static
+    {
+        self.tags.push((name.to_string(), Arc::new(tag)));
+        self
+    }
+
+    pub fn add_block<F>(&mut self, name: &str, block: F) -> &mut Self
+        where F: Fn(&str, &[Token], &[Element], &LiquidOptions) -> Result<Box<dyn Renderable>, Error> + Send + Sync + 'static
+    {
+        self.blocks.push((name.to_string(), Arc::new(block)));
+        self
+    }
+
+    /// Converts files with `extension`, e.g. `adoc`, into pages. A converter added later for the
+    /// same extension replaces the earlier one.
+    pub fn add_converter<C: Converter + 'static>(&mut self, extension: &str, converter: C) -> &mut Self {
+        self.converters.push((extension.trim_start_matches('.').to_string(), Arc::new(converter)));
+        self
+    }
+
+    /// The converter for files with `extension`, or `None` if they're copied as they are.
+    pub fn converter(&self, extension: &str) -> Option<Arc<dyn Converter>> {
+        self.converters.iter().rev()
+            .find(|(candidate, _)| candidate == extension)
+            .map(|(_, converter)| converter.clone())
+            .or_else(|| built_in_converter(extension))
+    }
+
+    pub fn register_filters(&self, context: &mut Context) {
+        for (name, filter) in &self.filters {
+            let filter = filter.clone();
+            context.add_filter(name, Box::new(move |input, args| filter(input, args)));
+        }
+    }
+
+    pub fn register_tags(&self, options: &mut LiquidOptions) {
+        for (name, tag) in &self.tags {
+            let tag = tag.clone();
+            options.tags.insert(name.clone(), Box::new(move |tag_name, arguments, options| tag(tag_name, arguments, options)));
+        }
+
+        for (name, block) in &self.blocks {
+            let block = block.clone();
+            options.blocks.insert(name.clone(), Box::new(move |block_name, arguments, elements, options| {
+                block(block_name, arguments, elements, options)
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use super::super::generation::{Page, PageGenerator};
+
+    struct Shout(Vec<Box<dyn Renderable>>);
+
+    impl Renderable for Shout {
+        fn render(&self, context: &mut Context) -> Result<Option<String>, Error> {
+            let mut output = String::new();
+            for element in &self.0 {
+                output.push_str(&element.render(context)?.unwrap_or_default());
+            }
+
+            Ok(Some(output.to_uppercase()))
+        }
+    }
+
+    struct Version;
+
+    impl Renderable for Version {
+        fn render(&self, _context: &mut Context) -> Result<Option<String>, Error> {
+            Ok(Some("1.0".to_string()))
+        }
+    }
+
+    struct Shouting;
+
+    impl Converter for Shouting {
+        fn convert(&self, contents: &str, _page: &Page) -> Result<String, io::Error> {
+            Ok(contents.to_uppercase())
+        }
+    }
+
+    #[test]
+    fn it_looks_up_custom_converters_before_built_in_ones() {
+        let mut extensions = TemplateExtensions::new();
+        extensions.add_converter(".shout", Shouting).add_converter("txt", Shouting);
+
+        let page = PageGenerator::new().parse_source("notes.txt", "").expect("Parse page");
+        assert_eq!("HI", extensions.converter("shout").expect("Custom converter").convert("hi", &page).expect("Convert"));
+        assert_eq!("HI", extensions.converter("txt").expect("Replaced converter").convert("hi", &page).expect("Convert"));
+        assert_eq!("html", extensions.converter("md").expect("Built-in converter").output_extension());
+        assert!(extensions.converter("css").is_none());
+    }
+
+    #[test]
+    fn it_registers_custom_filters_tags_and_blocks() {
+        let mut extensions = TemplateExtensions::new();
+        extensions
+            .add_filter("reverse_words", |input, _args| {
+                Ok(Value::Str(input.to_string().split_whitespace().rev().collect::<Vec<_>>().join(" ")))
+            })
+            .add_tag("version", |_name, _arguments, _options| Ok(Box::new(Version)))
+            .add_block("shout", |_name, _arguments, elements, options| Ok(Box::new(Shout(::liquid::parser::parse(elements, options)?))));
+
+        let mut options = LiquidOptions::default();
+        extensions.register_tags(&mut options);
+        let template = ::liquid::parse("{{ \"world hello\" | reverse_words }} v{% version %} {% shout %}hi {{ name }}{% endshout %}", options)
+            .expect("Parse template");
+
+        let mut context = Context::new();
+        context.set_val("name", Value::str("there"));
+        extensions.register_filters(&mut context);
+
+        assert_eq!("hello world v1.0 HI THERE", template.render(&mut context).expect("Render").expect("Output"));
+    }
+}
@@ -0,0 +1,360 @@
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde_json::{self, Map, Number, Value};
+use yaml_rust::yaml::{Hash, Yaml};
+
+use super::config::{Config, ExternalPluginConfig};
+use super::generation::Page;
+use super::plugins::Plugin;
+use super::site::Site;
+
+/// The hooks an external plugin can ask to be run at.
+pub const HOOKS: &'static [&'static str] = &["generate", "before_render", "after_render", "after_build"];
+
+/// How many seconds an external plugin may take, unless its config says otherwise.
+pub const DEFAULT_TIMEOUT: u64 = 30;
+
+/// A plugin run as a separate program. At each of its hooks the command is started with a JSON
+/// request on stdin, and may answer with a JSON object on stdout:
+///
+/// - `generate` gets the `site` config and every page, and may return `pages`, a list of
+///   `{"path": ..., "content": ...}` to add to the site.
+/// - `before_render` gets a `page`, and may return its new `front_matter` and `content`.
+/// - `after_render` gets a `page` and its `html`, and may return new `html`.
+/// - `after_build` gets every page and the `output` directory; its answer is ignored.
+///
+/// Each page is sent as its source `path`, `url`, `front_matter` and `content`. A command that
+/// exits with an error, takes too long or answers with anything but JSON fails the build.
+pub struct ExternalPlugin {
+    config: ExternalPluginConfig,
+    /// The site config, sent with every request.
+    site: Yaml,
+    /// Where the command is run from.
+    root_dir: PathBuf,
+}
+
+impl ExternalPlugin {
+    pub fn new(config: ExternalPluginConfig) -> Self {
+        ExternalPlugin {
+            config: config,
+            site: Yaml::Hash(Hash::new()),
+            root_dir: PathBuf::from("."),
+        }
+    }
+
+    fn runs_at(&self, hook: &str) -> bool {
+        self.config.hooks.iter().any(|configured| configured == hook)
+    }
+
+    /// Runs the command with a request for `hook`, returning its answer, or an empty mapping if it
+    /// printed nothing.
+    fn run(&self, hook: &str, fields: Vec<(&str, Value)>) -> Result<Yaml, io::Error> {
+        let mut request = Map::new();
+        request.insert("hook".to_string(), Value::String(hook.to_string()));
+        request.insert("site".to_string(), to_json(&self.site));
+        for (key, value) in fields {
+            request.insert(key.to_string(), value);
+        }
+        let request = format!("{}\n", Value::Object(request));
+
+        let output = run_command(&self.config.command, &self.root_dir, request, Duration::from_secs(self.config.timeout))
+            .map_err(|what| io::Error::new(what.kind(), format!("{}: {}", hook, what)))?;
+
+        if output.trim().is_empty() {
+            return Ok(Yaml::Hash(Hash::new()));
+        }
+
+        match parse_json(&output) {
+            Ok(response @ Yaml::Hash(_)) => Ok(response),
+            Ok(_) => Err(invalid_response(hook, "expected a JSON object")),
+            Err(what) => Err(invalid_response(hook, &what))
+        }
+    }
+}
+
+impl Plugin for ExternalPlugin {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn after_config(&mut self, config: &mut Config) -> Result<(), io::Error> {
+        self.site = config.site.clone();
+        self.root_dir = PathBuf::from(&config.root_dir);
+        Ok(())
+    }
+
+    fn generate(&mut self, site: &mut Site) -> Result<(), io::Error> {
+        if !self.runs_at("generate") {
+            return Ok(());
+        }
+
+        let response = self.run("generate", vec![("pages", pages_json(&site.pages))])?;
+
+        let pages = match response["pages"] {
+            Yaml::Array(ref pages) => pages.clone(),
+            Yaml::BadValue => Vec::new(),
+            _ => return Err(invalid_response("generate", "`pages` should be a list"))
+        };
+
+        for page in pages {
+            match (page["path"].as_str(), page["content"].as_str()) {
+                (Some(path), Some(content)) => site.add_page(path, content)?,
+                _ => return Err(invalid_response("generate", "each of `pages` needs a `path` and `content`"))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn before_render(&mut self, page: &mut Page) -> Result<(), io::Error> {
+        if !self.runs_at("before_render") {
+            return Ok(());
+        }
+
+        let response = self.run("before_render", vec![("page", page_json(page))])?;
+
+        match response["front_matter"] {
+            Yaml::Hash(_) => page.front_matter = response["front_matter"].clone(),
+            Yaml::BadValue => {},
+            _ => return Err(invalid_response("before_render", "`front_matter` should be an object"))
+        }
+
+        match response["content"] {
+            Yaml::String(ref content) => page.contents = content.clone(),
+            Yaml::BadValue => {},
+            _ => return Err(invalid_response("before_render", "`content` should be a string"))
+        }
+
+        Ok(())
+    }
+
+    fn after_render(&mut self, page: &Page, html: &mut String) -> Result<(), io::Error> {
+        if !self.runs_at("after_render") {
+            return Ok(());
+        }
+
+        let response = self.run("after_render", vec![("page", page_json(page)), ("html", Value::String(html.clone()))])?;
+
+        match response["html"] {
+            Yaml::String(ref new_html) => *html = new_html.clone(),
+            Yaml::BadValue => {},
+            _ => return Err(invalid_response("after_render", "`html` should be a string"))
+        }
+
+        Ok(())
+    }
+
+    fn after_build(&mut self, site: &Site, output_dir: &Path) -> Result<(), io::Error> {
+        if !self.runs_at("after_build") {
+            return Ok(());
+        }
+
+        let fields = vec![("pages", pages_json(&site.pages)), ("output", Value::String(output_dir.to_string_lossy().into_owned()))];
+        self.run("after_build", fields).map(|_| ())
+    }
+}
+
+fn invalid_response(hook: &str, what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: invalid response: {}", hook, what))
+}
+
+/// Runs `command` through the shell from `dir`, feeding it `input` and returning what it printed.
+/// Fails if it can't be started, exits unsuccessfully or runs for longer than `timeout`.
+fn run_command(command: &str, dir: &Path, input: String, timeout: Duration) -> Result<String, io::Error> {
+    let mut child = shell(command)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|what| io::Error::new(what.kind(), format!("couldn't run `{}`: {}", command, what)))?;
+
+    // Feed and drain the pipes on their own threads, so a command with a lot to say can't block
+    let mut stdin = child.stdin.take().expect("Piped stdin");
+    let writer = thread::spawn(move || {
+        // A command that doesn't read its input closes the pipe early, which isn't an error
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let stdout = read_to_end(child.stdout.take().expect("Piped stdout"));
+    let stderr = read_to_end(child.stderr.take().expect("Piped stderr"));
+
+    // Anything the command started may still hold the pipes open once it's killed, so the
+    // threads are left to finish on their own rather than waited for
+    let status = match wait_with_timeout(&mut child, timeout)? {
+        Some(status) => status,
+        None => return Err(io::Error::new(io::ErrorKind::TimedOut, format!("`{}` timed out after {}s", command, timeout.as_secs())))
+    };
+
+    let _ = writer.join();
+    let stdout = stdout.join().expect("Stdout reader");
+    let stderr = stderr.join().expect("Stderr reader");
+
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr);
+        let details = if stderr.trim().is_empty() { String::new() } else { format!(":\n{}", stderr.trim_end()) };
+
+        return Err(io::Error::other(format!("`{}` exited with {}{}", command, status, details)));
+    }
+
+    String::from_utf8(stdout).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("`{}` printed invalid UTF-8", command)))
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+fn read_to_end<R: Read + Send + 'static>(mut reader: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = reader.read_to_end(&mut output);
+        output
+    })
+}
+
+/// Waits for `child` to exit, killing it if it's still running after `timeout`. Returns `None`
+/// if it had to be killed.
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<Option<::std::process::ExitStatus>, io::Error> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn pages_json(pages: &[Page]) -> Value {
+    Value::Array(pages.iter().map(page_json).collect())
+}
+
+fn page_json(page: &Page) -> Value {
+    let front_matter = match page.front_matter {
+        Yaml::Hash(_) => to_json(&page.front_matter),
+        _ => Value::Object(Map::new())
+    };
+
+    let mut json = Map::new();
+    json.insert("path".to_string(), Value::String(page.source_path().to_string_lossy().into_owned()));
+    json.insert("url".to_string(), Value::String(page.url().to_string()));
+    json.insert("front_matter".to_string(), front_matter);
+    json.insert("content".to_string(), Value::String(page.contents.clone()));
+    Value::Object(json)
+}
+
+/// Converts YAML to JSON. Keys that aren't strings are written as strings, and anything with no
+/// JSON equivalent, such as an infinite number, as `null`.
+pub fn to_json(yaml: &Yaml) -> Value {
+    match *yaml {
+        Yaml::Real(ref real) => real.parse::<f64>().ok().and_then(Number::from_f64).map(Value::Number).unwrap_or(Value::Null),
+        Yaml::Integer(integer) => Value::Number(integer.into()),
+        Yaml::String(ref string) => Value::String(string.clone()),
+        Yaml::Boolean(boolean) => Value::Bool(boolean),
+        Yaml::Array(ref items) => Value::Array(items.iter().map(to_json).collect()),
+        Yaml::Hash(ref hash) => {
+            let members = hash.iter()
+                .map(|(key, value)| {
+                    let key = match *key {
+                        Yaml::String(ref key) => key.clone(),
+                        ref other => to_json(other).to_string()
+                    };
+
+                    (key, to_json(value))
+                })
+                .collect();
+
+            Value::Object(members)
+        },
+        Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => Value::Null
+    }
+}
+
+/// Converts JSON to YAML, so a plugin's answer can be used like front matter.
+pub fn from_json(json: Value) -> Yaml {
+    match json {
+        Value::Null => Yaml::Null,
+        Value::Bool(boolean) => Yaml::Boolean(boolean),
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => Yaml::Integer(integer),
+            None => Yaml::Real(number.to_string())
+        },
+        Value::String(string) => Yaml::String(string),
+        Value::Array(items) => Yaml::Array(items.into_iter().map(from_json).collect()),
+        Value::Object(members) => Yaml::Hash(members.into_iter().map(|(key, value)| (Yaml::String(key), from_json(value))).collect())
+    }
+}
+
+/// Reads a JSON document into YAML values.
+pub fn parse_json(json: &str) -> Result<Yaml, String> {
+    serde_json::from_str(json).map(from_json).map_err(|what| what.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn it_converts_between_json_and_yaml() {
+        let yaml = YamlLoader::load_from_str("title: \"Say \\\"hi\\\"\\n\"\ntags: [a, b]\ncount: 3\nratio: 0.5\ndraft: false\nnothing: ~")
+            .expect("Parse YAML").remove(0);
+
+        let json = to_json(&yaml).to_string();
+        assert_eq!("{\"title\":\"Say \\\"hi\\\"\\n\",\"tags\":[\"a\",\"b\"],\"count\":3,\"ratio\":0.5,\"draft\":false,\"nothing\":null}", json);
+        assert_eq!(yaml, parse_json(&json).expect("Parse JSON"));
+
+        assert_eq!(Value::Null, to_json(&Yaml::Real("1e400".to_string())));
+    }
+
+    #[test]
+    fn it_parses_json_escapes() {
+        let parsed = parse_json("{ \"path\" : \"a\\/b \\u00e9 \\ud83d\\ude00\", \"items\": [ ] }").expect("Parse JSON");
+
+        assert_eq!(Some("a/b é 😀"), parsed["path"].as_str());
+        assert_eq!(Some(&Vec::new()), parsed["items"].as_vec());
+        assert!(parse_json("{\"unterminated\": \"").is_err());
+        assert!(parse_json("{\"a\": 1} trailing").is_err());
+        assert!(parse_json("{'single': 1}").is_err());
+        assert!(parse_json("\"\\ud83d\\u0041\"").is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn it_runs_commands_with_a_timeout() {
+        let output = run_command("tr a-z A-Z", Path::new("."), "shout".to_string(), Duration::from_secs(5)).expect("Run command");
+        assert_eq!("SHOUT", output);
+
+        let started = Instant::now();
+        let error = run_command("sleep 5", Path::new("."), String::new(), Duration::from_millis(100)).expect_err("Time out");
+        assert_eq!(io::ErrorKind::TimedOut, error.kind());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let error = run_command("echo 'bad input' >&2; exit 2", Path::new("."), String::new(), Duration::from_secs(5)).expect_err("Fail");
+        assert!(error.to_string().contains("exited with exit status: 2:\nbad input"));
+
+        let output = run_command("ls", Path::new("tests/fixtures/external-plugins"), String::new(), Duration::from_secs(5)).expect("Run command");
+        assert!(output.lines().any(|line| line == "_plugins"));
+    }
+}
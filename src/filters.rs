@@ -0,0 +1,569 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+use liquid::{FilterError, Value};
+use pulldown_cmark::{html, Options, Parser};
+use serde_json;
+use time;
+
+use super::generation::parse_date;
+
+/// Prefixes a site-relative path with the configured base path, e.g. `about/` becomes
+/// `/docs/about/`. Full URLs are passed through untouched.
+pub fn relative_url(base_path: &str, input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_no_arguments("relative_url", args)?;
+
+    let input = input.to_string();
+    if is_full_url(&input) {
+        return Ok(Value::Str(input));
+    }
+
+    Ok(Value::Str(join_url(base_path, &input)))
+}
+
+/// Like `relative_url`, but also prefixes the configured base URL to produce a full URL.
+pub fn absolute_url(base_url: &str, base_path: &str, input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_no_arguments("absolute_url", args)?;
+
+    let input = input.to_string();
+    if is_full_url(&input) {
+        return Ok(Value::Str(input));
+    }
+
+    Ok(Value::Str(format!("{}{}", base_url.trim_end_matches('/'), join_url(base_path, &input))))
+}
+
+/// Formats a date with a `strftime` format string, e.g. `{{ page.date | date: "%B %-d, %Y" }}`.
+/// Accepts front matter dates, Unix timestamps and `now`, and formats them in UTC.
+pub fn date(input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_argument_count("date", args, 1, 1)?;
+
+    let timespec = match *input {
+        Value::Num(timestamp) => time::Timespec::new(timestamp as i64, 0),
+        Value::Str(ref date) if date == "now" || date == "today" => time::get_time(),
+        Value::Str(ref date) => parse_date(date)
+            .ok_or_else(|| FilterError::InvalidType(format!("date expects a date, got `{}`", date)))?,
+        _ => return Err(FilterError::InvalidType("date expects a date string or timestamp".to_string()))
+    };
+
+    let format = args[0].to_string();
+    strftime(&time::at_utc(timespec), &format)
+        .map(Value::Str)
+        .map_err(|what| FilterError::InvalidArgument(0, format!("invalid date format `{}`: {}", format, what)))
+}
+
+/// Like `Tm::strftime`, but also understands Ruby's `%-d` style flag for dropping padding.
+fn strftime(tm: &time::Tm, format: &str) -> Result<String, time::ParseError> {
+    let mut formatted = String::new();
+    let mut rest = format;
+
+    while let Some(index) = rest.find("%-") {
+        formatted.push_str(&tm.strftime(&rest[..index])?.to_string());
+
+        let specifier = rest[index + 2..].chars().next().map(|c| format!("%{}", c)).unwrap_or_else(|| "%".to_string());
+        let padded = tm.strftime(&specifier)?.to_string();
+        let unpadded = padded.trim_start_matches(&['0', ' '][..]);
+        formatted.push_str(if unpadded.is_empty() { "0" } else { unpadded });
+
+        rest = &rest[(index + 1 + specifier.len()).min(rest.len())..];
+    }
+
+    formatted.push_str(&tm.strftime(rest)?.to_string());
+    Ok(formatted)
+}
+
+/// Renders a string of Markdown to HTML.
+pub fn markdownify(parse_options: Options, input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_no_arguments("markdownify", args)?;
+
+    let input = input.to_string();
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, Parser::new_ext(&input, parse_options));
+
+    Ok(Value::Str(rendered))
+}
+
+/// Turns a string into a lowercase, URL-friendly slug, e.g. `Hello, World!` becomes
+/// `hello-world`. The optional mode is Jekyll's: `default`, `raw`, `pretty`, `ascii` or `none`.
+pub fn slugify(input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_argument_count("slugify", args, 0, 1)?;
+
+    let mode = args.first().map(|mode| mode.to_string()).unwrap_or_else(|| "default".to_string());
+    let keep: fn(char) -> bool = match mode.as_str() {
+        "none" => return Ok(Value::Str(input.to_string())),
+        "raw" => |c: char| !c.is_whitespace(),
+        "default" => |c: char| c.is_alphanumeric(),
+        "pretty" => |c: char| c.is_alphanumeric() || "._~!$&'()+,;=@".contains(c),
+        "ascii" => |c: char| c.is_ascii_alphanumeric(),
+        _ => return Err(FilterError::InvalidArgument(0, format!("unknown slugify mode `{}`", mode)))
+    };
+
+    let slug = input.to_string()
+        .split(|c: char| !keep(c))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase();
+
+    Ok(Value::Str(slug))
+}
+
+/// Escapes a string for use in XML, such as a feed.
+pub fn xml_escape(input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_no_arguments("xml_escape", args)?;
+
+    Ok(Value::Str(escape_xml(&input.to_string())))
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Converts a value to JSON. Object keys are sorted so the output is stable between builds.
+pub fn jsonify(input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_no_arguments("jsonify", args)?;
+
+    Ok(Value::Str(to_json(input).to_string()))
+}
+
+/// Keeps the items of an array whose property equals a value, or contains it if the property is
+/// an array, e.g. `{{ site.members | where: "team", "docs" }}`.
+pub fn where_filter(input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_argument_count("where", args, 2, 2)?;
+
+    let (property_name, expected) = (args[0].to_string(), args[1].to_string());
+    let items = input_array("where", input)?;
+
+    let matching = items.iter()
+        .filter(|item| match property(item, &property_name) {
+            Some(Value::Array(values)) => values.iter().any(|value| value.to_string() == expected),
+            Some(value) => value.to_string() == expected,
+            None => false
+        })
+        .cloned()
+        .collect();
+
+    Ok(Value::Array(matching))
+}
+
+/// Keeps the items of an array for which a condition holds, e.g.
+/// `{{ site.members | where_exp: "member", "member.age >= 18 and member.team != 'ops'" }}`.
+/// Conditions compare with `==`, `!=`, `<`, `>`, `<=`, `>=` and `contains`, joined by `and` and
+/// `or`.
+pub fn where_exp(input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_argument_count("where_exp", args, 2, 2)?;
+
+    let (variable, condition) = (args[0].to_string(), args[1].to_string());
+    let items = input_array("where_exp", input)?;
+
+    let mut matching = Vec::new();
+    for item in items {
+        if evaluate_condition(&condition, &variable, item)? {
+            matching.push(item.clone());
+        }
+    }
+
+    Ok(Value::Array(matching))
+}
+
+/// Groups the items of an array by a property. Each group has the property's value as `name`,
+/// its `items` and their `size`, in the order the values first appear.
+pub fn group_by(input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_argument_count("group_by", args, 1, 1)?;
+
+    let property_name = args[0].to_string();
+    let items = input_array("group_by", input)?;
+
+    let mut groups: Vec<(String, Vec<Value>)> = Vec::new();
+    for item in items {
+        let name = property(item, &property_name).map(|value| value.to_string()).unwrap_or_default();
+
+        match groups.iter().position(|(group, _)| *group == name) {
+            Some(index) => groups[index].1.push(item.clone()),
+            None => groups.push((name, vec![item.clone()]))
+        }
+    }
+
+    let groups = groups.into_iter()
+        .map(|(name, items)| {
+            let mut group = HashMap::new();
+            group.insert("name".to_string(), Value::Str(name));
+            group.insert("size".to_string(), Value::Num(items.len() as f32));
+            group.insert("items".to_string(), Value::Array(items));
+
+            Value::Object(group)
+        })
+        .collect();
+
+    Ok(Value::Array(groups))
+}
+
+/// Sorts an array, optionally by a property of its items. Items missing the property go last,
+/// unless the second argument is `"first"`.
+pub fn sort(input: &Value, args: &[Value]) -> Result<Value, FilterError> {
+    check_argument_count("sort", args, 0, 2)?;
+
+    let property_name = args.first().map(|name| name.to_string());
+    let nils_first = match args.get(1).map(|nils| nils.to_string()) {
+        None => false,
+        Some(ref nils) if nils == "last" => false,
+        Some(ref nils) if nils == "first" => true,
+        Some(nils) => return Err(FilterError::InvalidArgument(1, format!("sort expects \"first\" or \"last\", got `{}`", nils)))
+    };
+
+    let mut sorted = input_array("sort", input)?.clone();
+    sorted.sort_by(|a, b| {
+        let (a, b) = match property_name {
+            Some(ref name) => (property(a, name), property(b, name)),
+            None => (Some(a), Some(b))
+        };
+
+        match (a, b) {
+            (Some(a), Some(b)) => compare(a, b),
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => if nils_first { Ordering::Less } else { Ordering::Greater },
+            (Some(_), None) => if nils_first { Ordering::Greater } else { Ordering::Less }
+        }
+    });
+
+    Ok(Value::Array(sorted))
+}
+
+fn input_array<'a>(filter_name: &str, input: &'a Value) -> Result<&'a Vec<Value>, FilterError> {
+    input.as_array().ok_or_else(|| FilterError::InvalidType(format!("{} expects an array", filter_name)))
+}
+
+/// Looks up a dotted property path, e.g. `author.name`, in an object.
+fn property<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| value.as_object().and_then(|object| object.get(key)))
+}
+
+fn compare(a: &Value, b: &Value) -> Ordering {
+    a.partial_cmp(b).unwrap_or_else(|| a.to_string().cmp(&b.to_string()))
+}
+
+fn evaluate_condition(condition: &str, variable: &str, item: &Value) -> Result<bool, FilterError> {
+    for alternative in split_keyword(condition, "or") {
+        let mut all = true;
+        for comparison in split_keyword(&alternative, "and") {
+            if !evaluate_comparison(&comparison, variable, item)? {
+                all = false;
+                break;
+            }
+        }
+
+        if all {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn evaluate_comparison(comparison: &str, variable: &str, item: &Value) -> Result<bool, FilterError> {
+    let tokens = tokenize(comparison);
+
+    match tokens.len() {
+        1 => Ok(is_truthy(operand(&tokens[0], variable, item).as_ref())),
+        3 => {
+            let left = operand(&tokens[0], variable, item);
+            let right = operand(&tokens[2], variable, item);
+
+            match (tokens[1].as_str(), left, right) {
+                ("==", left, right) => Ok(left == right),
+                ("!=", left, right) => Ok(left != right),
+                ("contains", Some(Value::Array(values)), Some(right)) => Ok(values.contains(&right)),
+                ("contains", Some(Value::Str(text)), Some(right)) => Ok(text.contains(&right.to_string())),
+                ("contains", _, _) => Ok(false),
+                (operator, Some(left), Some(right)) => match (operator, left.partial_cmp(&right)) {
+                    ("<", Some(ordering)) => Ok(ordering == Ordering::Less),
+                    (">", Some(ordering)) => Ok(ordering == Ordering::Greater),
+                    ("<=", Some(ordering)) => Ok(ordering != Ordering::Greater),
+                    (">=", Some(ordering)) => Ok(ordering != Ordering::Less),
+                    ("<", None) | (">", None) | ("<=", None) | (">=", None) => Ok(false),
+                    _ => Err(unknown_operator(operator))
+                },
+                ("<", _, _) | (">", _, _) | ("<=", _, _) | (">=", _, _) => Ok(false),
+                (operator, _, _) => Err(unknown_operator(operator))
+            }
+        },
+        _ => Err(FilterError::InvalidArgument(1, format!("where_exp can't understand `{}`", comparison.trim())))
+    }
+}
+
+fn unknown_operator(operator: &str) -> FilterError {
+    FilterError::InvalidArgument(1, format!("where_exp doesn't know the operator `{}`", operator))
+}
+
+/// Resolves a token in a `where_exp` condition: a quoted string, number, boolean, `nil` or a
+/// property of the item being tested.
+fn operand(token: &str, variable: &str, item: &Value) -> Option<Value> {
+    if token.len() >= 2 && (token.starts_with('"') && token.ends_with('"') || token.starts_with('\'') && token.ends_with('\'')) {
+        return Some(Value::Str(token[1..token.len() - 1].to_string()));
+    }
+
+    match token {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        "nil" | "null" | "empty" => return None,
+        _ => {}
+    }
+
+    if let Ok(number) = token.parse::<f32>() {
+        return Some(Value::Num(number));
+    }
+
+    if token == variable {
+        return Some(item.clone());
+    }
+
+    let prefix = format!("{}.", variable);
+    if token.starts_with(&prefix) {
+        property(item, &token[prefix.len()..]).cloned()
+    } else {
+        None
+    }
+}
+
+fn is_truthy(value: Option<&Value>) -> bool {
+    !matches!(value, None | Some(&Value::Bool(false)))
+}
+
+/// Splits a condition on whitespace, keeping quoted strings together.
+fn tokenize(condition: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+
+    for c in condition.chars() {
+        match quote {
+            Some(open) => {
+                current.push(c);
+                if c == open {
+                    quote = None;
+                }
+            },
+            None if c == '"' || c == '\'' => {
+                current.push(c);
+                quote = Some(c);
+            },
+            None if c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(current.clone());
+                    current.clear();
+                }
+            },
+            None => current.push(c)
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+/// Splits a condition on a keyword like `and`, outside of quoted strings.
+fn split_keyword(condition: &str, keyword: &str) -> Vec<String> {
+    let mut parts = vec![Vec::new()];
+
+    for token in tokenize(condition) {
+        if token == keyword {
+            parts.push(Vec::new());
+        } else {
+            parts.last_mut().expect("There's always a part").push(token);
+        }
+    }
+
+    parts.into_iter().map(|tokens| tokens.join(" ")).collect()
+}
+
+/// Converts a Liquid value to JSON, with object keys sorted. Numbers JSON can't represent, like
+/// infinity, become `null`.
+fn to_json(value: &Value) -> serde_json::Value {
+    match *value {
+        Value::Num(number) if number.fract() == 0.0 && number.abs() < 1e15 => serde_json::Value::from(number as i64),
+        // Going through the shortest decimal keeps `0.1` from widening into `0.10000000149011612`
+        Value::Num(number) => format!("{}", number).parse().ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number),
+        Value::Bool(boolean) => serde_json::Value::Bool(boolean),
+        Value::Str(ref string) => serde_json::Value::String(string.clone()),
+        Value::Array(ref items) => serde_json::Value::Array(items.iter().map(to_json).collect()),
+        Value::Object(ref object) => {
+            let sorted: BTreeMap<_, _> = object.iter().map(|(key, value)| (key.clone(), to_json(value))).collect();
+            serde_json::Value::Object(sorted.into_iter().collect())
+        }
+    }
+}
+
+fn join_url(base_path: &str, path: &str) -> String {
+    let base_path = base_path.trim_end_matches('/');
+
+    if path.starts_with('/') {
+        format!("{}{}", base_path, path)
+    } else {
+        format!("{}/{}", base_path, path)
+    }
+}
+
+fn is_full_url(input: &str) -> bool {
+    if input.starts_with("//") {
+        return true;
+    }
+
+    match input.find("://") {
+        Some(index) if index > 0 => input[..index].chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.'),
+        _ => false
+    }
+}
+
+fn check_no_arguments(filter_name: &str, args: &[Value]) -> Result<(), FilterError> {
+    if args.is_empty() {
+        Ok(())
+    } else {
+        Err(FilterError::InvalidArgumentCount(format!("{} expects no arguments, got {}", filter_name, args.len())))
+    }
+}
+
+fn check_argument_count(filter_name: &str, args: &[Value], min: usize, max: usize) -> Result<(), FilterError> {
+    if args.len() < min || args.len() > max {
+        let expected = if min == max { min.to_string() } else { format!("{} to {}", min, max) };
+        return Err(FilterError::InvalidArgumentCount(format!("{} expects {} arguments, got {}", filter_name, expected, args.len())));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_prefixes_the_base_path_to_relative_urls() {
+        assert_eq!(Value::str("/docs/css/site.css"), relative_url("/docs", &Value::str("css/site.css"), &[]).unwrap());
+        assert_eq!(Value::str("/docs/about/"), relative_url("/docs", &Value::str("/about/"), &[]).unwrap());
+        assert_eq!(Value::str("/about/"), relative_url("", &Value::str("about/"), &[]).unwrap());
+    }
+
+    #[test]
+    fn it_builds_absolute_urls_from_the_base_url_and_path() {
+        let url = absolute_url("https://example.com/", "/docs", &Value::str("about/"), &[]).unwrap();
+
+        assert_eq!(Value::str("https://example.com/docs/about/"), url);
+    }
+
+    #[test]
+    fn it_leaves_full_urls_alone() {
+        let url = Value::str("https://cdn.example.com/site.css");
+
+        assert_eq!(url, relative_url("/docs", &url, &[]).unwrap());
+        assert_eq!(url, absolute_url("https://example.com", "/docs", &url, &[]).unwrap());
+    }
+
+    #[test]
+    fn it_rejects_arguments_to_url_filters() {
+        assert!(relative_url("/docs", &Value::str("about/"), &[Value::str("extra")]).is_err());
+    }
+
+    fn object(pairs: &[(&str, Value)]) -> Value {
+        Value::Object(pairs.iter().map(|(key, value)| (key.to_string(), value.clone())).collect())
+    }
+
+    fn members() -> Value {
+        Value::Array(vec![
+            object(&[("name", Value::str("Ada")), ("team", Value::str("docs")), ("age", Value::Num(36.0)),
+                     ("tags", Value::Array(vec![Value::str("rust")]))]),
+            object(&[("name", Value::str("Grace")), ("team", Value::str("ops")), ("age", Value::Num(45.0))]),
+            object(&[("name", Value::str("Alan")), ("team", Value::str("docs"))]),
+        ])
+    }
+
+    fn names(value: Value) -> Vec<String> {
+        value.as_array().expect("Array").iter().map(|item| item.as_object().expect("Object")["name"].to_string()).collect()
+    }
+
+    #[test]
+    fn it_formats_dates() {
+        assert_eq!(Value::str("March 1, 2017"), date(&Value::str("2017-03-01 10:30 +01:00"), &[Value::str("%B %-d, %Y")]).unwrap());
+        assert_eq!(Value::str("2017-03-01T09:30:00Z"), date(&Value::str("2017-03-01T09:30:00Z"), &[Value::str("%Y-%m-%dT%H:%M:%SZ")]).unwrap());
+        assert_eq!(Value::str("1970-01-02"), date(&Value::Num(86400.0), &[Value::str("%F")]).unwrap());
+        assert_eq!(Value::str("1 Mar, 9:05"), date(&Value::str("2017-03-01 09:05"), &[Value::str("%-d %b, %-H:%M")]).unwrap());
+        assert!(date(&Value::str("yesterday"), &[Value::str("%F")]).is_err());
+    }
+
+    #[test]
+    fn it_renders_markdown() {
+        assert_eq!(Value::str("<p>Some <em>emphasis</em></p>\n"), markdownify(Options::empty(), &Value::str("Some *emphasis*"), &[]).unwrap());
+    }
+
+    #[test]
+    fn it_slugifies_strings() {
+        assert_eq!(Value::str("hello-world-2017"), slugify(&Value::str("Hello, World! 2017"), &[]).unwrap());
+        assert_eq!(Value::str("the-_config.yml-file"), slugify(&Value::str("The _config.yml file"), &[Value::str("pretty")]).unwrap());
+        assert_eq!(Value::str("café-au-lait"), slugify(&Value::str("Café au lait"), &[]).unwrap());
+        assert_eq!(Value::str("caf-au-lait"), slugify(&Value::str("Café au lait"), &[Value::str("ascii")]).unwrap());
+        assert_eq!(Value::str("Keep Me"), slugify(&Value::str("Keep Me"), &[Value::str("none")]).unwrap());
+        assert!(slugify(&Value::str("x"), &[Value::str("fancy")]).is_err());
+    }
+
+    #[test]
+    fn it_escapes_xml() {
+        assert_eq!(Value::str("Tom &amp; &quot;Jerry&quot; &lt;3 &apos;em&apos;"), xml_escape(&Value::str("Tom & \"Jerry\" <3 'em'"), &[]).unwrap());
+    }
+
+    #[test]
+    fn it_converts_values_to_json() {
+        let value = object(&[("title", Value::str("Say \"hi\"\n")), ("count", Value::Num(3.0)), ("ratio", Value::Num(0.5)),
+                             ("tags", Value::Array(vec![Value::str("a"), Value::Bool(true)]))]);
+
+        assert_eq!(Value::str(r#"{"count":3,"ratio":0.5,"tags":["a",true],"title":"Say \"hi\"\n"}"#), jsonify(&value, &[]).unwrap());
+        assert_eq!(Value::str("[null,null,0.1]"),
+                   jsonify(&Value::Array(vec![Value::Num(f32::INFINITY), Value::Num(f32::NAN), Value::Num(0.1)]), &[]).unwrap());
+    }
+
+    #[test]
+    fn it_filters_arrays_by_property() {
+        assert_eq!(vec!["Ada", "Alan"], names(where_filter(&members(), &[Value::str("team"), Value::str("docs")]).unwrap()));
+        assert_eq!(vec!["Ada"], names(where_filter(&members(), &[Value::str("tags"), Value::str("rust")]).unwrap()));
+        assert!(where_filter(&Value::str("not an array"), &[Value::str("team"), Value::str("docs")]).is_err());
+    }
+
+    #[test]
+    fn it_filters_arrays_by_expression() {
+        let filter = |condition: &str| names(where_exp(&members(), &[Value::str("member"), Value::str(condition)]).unwrap());
+
+        assert_eq!(vec!["Grace"], filter("member.age > 40"));
+        assert_eq!(vec!["Ada"], filter("member.team == 'docs' and member.age"));
+        assert_eq!(vec!["Ada", "Grace"], filter("member.tags contains \"rust\" or member.team == \"ops\""));
+        assert_eq!(vec!["Grace", "Alan"], filter("member.name != 'Ada'"));
+        assert!(where_exp(&members(), &[Value::str("member"), Value::str("member.age ~ 3")]).is_err());
+    }
+
+    #[test]
+    fn it_groups_arrays_by_property() {
+        let groups = group_by(&members(), &[Value::str("team")]).unwrap();
+        let groups = groups.as_array().expect("Array");
+
+        assert_eq!(2, groups.len());
+        assert_eq!(Value::str("docs"), groups[0].as_object().unwrap()["name"]);
+        assert_eq!(Value::Num(2.0), groups[0].as_object().unwrap()["size"]);
+        assert_eq!(vec!["Alan"], names(groups[0].as_object().unwrap()["items"].clone())[1..].to_vec());
+        assert_eq!(Value::str("ops"), groups[1].as_object().unwrap()["name"]);
+    }
+
+    #[test]
+    fn it_sorts_arrays_by_property() {
+        assert_eq!(vec!["Ada", "Grace", "Alan"], names(sort(&members(), &[Value::str("age")]).unwrap()));
+        assert_eq!(vec!["Alan", "Ada", "Grace"], names(sort(&members(), &[Value::str("age"), Value::str("first")]).unwrap()));
+        assert_eq!(vec!["Ada", "Alan", "Grace"], names(sort(&members(), &[Value::str("name")]).unwrap()));
+
+        let numbers = Value::Array(vec![Value::Num(3.0), Value::Num(1.0), Value::Num(2.0)]);
+        assert_eq!(Value::Array(vec![Value::Num(1.0), Value::Num(2.0), Value::Num(3.0)]), sort(&numbers, &[]).unwrap());
+    }
+}
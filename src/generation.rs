@@ -1,25 +1,59 @@
 use std::io;
-use std::io::prelude::Read;
-use std::fs::File;
 use std::default::Default;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-use pulldown_cmark::{Parser, html, Options};
+use pulldown_cmark::Options;
 
 use regex::Regex;
 
-use yaml_rust::YamlLoader;
+use yaml_rust::{ScanError, YamlLoader};
 use yaml_rust::yaml::Yaml;
 
-use liquid::{Renderable, Context};
+use liquid::{Renderable, Context, LiquidOptions};
+
+use time::{self, Timespec, Tm};
+
+use super::converters::{source_extension, Converter};
+use super::extensions::TemplateExtensions;
+use super::filters;
+use super::tags::{self, PageUrls, Stash};
+use super::git::{self, FileHistory};
+use super::config::merge_yaml;
+use super::site::Layout;
+use super::vfs::{DiskFileSystem, FileSystem};
+
+/// Ends a page's excerpt unless the config or the page's front matter picks another marker.
+pub const DEFAULT_EXCERPT_SEPARATOR: &'static str = "<!--more-->";
+
+/// The reading speed `reading_time` assumes.
+pub const WORDS_PER_MINUTE: usize = 200;
 
 lazy_static! {
     static ref FRONT_MATTER_REGEX: Regex = Regex::new(r"(?s)^(?:---)\s+(.*)\s+(?:---)\s+(.*)").unwrap();
+    static ref LIQUID_MARKUP_REGEX: Regex = Regex::new(r"(?s)\{%.*?%\}|\{\{.*?\}\}").unwrap();
+    static ref HTML_TAG_REGEX: Regex = Regex::new(r"<[^>]*>").unwrap();
+    static ref DATE_REGEX: Regex = Regex::new(
+        r"^(\d{4})-(\d{2})-(\d{2})(?:[T ](\d{2}):(\d{2})(?::(\d{2}))?)?\s*(?:(Z)|([+-])(\d{2}):?(\d{2}))?$"
+    ).unwrap();
 }
 
+#[derive(Clone)]
 pub struct PageGenerator {
     input_file: String,
-    output_file: String,
+    source_path: String,
     parse_options: Options,
+    base_url: String,
+    base_path: String,
+    site: Yaml,
+    front_matter_defaults: Yaml,
+    excerpt_separator: String,
+    url: String,
+    collection: String,
+    git_dates: bool,
+    page_urls: Arc<PageUrls>,
+    extensions: TemplateExtensions,
+    file_system: Arc<dyn FileSystem>,
 }
 
 impl PageGenerator {
@@ -32,8 +66,10 @@ impl PageGenerator {
         self
     }
 
-    pub fn set_output_file<S: Into<String>>(&mut self, output_file: S) -> &mut Self {
-        self.output_file = output_file.into();
+    /// Sets the page's path relative to the source directory, e.g. `blog/hello.md`, which templates
+    /// see as `page.path`. It's the input file unless set.
+    pub fn set_source_path<S: Into<String>>(&mut self, source_path: S) -> &mut Self {
+        self.source_path = source_path.into();
         self
     }
 
@@ -42,34 +78,168 @@ impl PageGenerator {
         self
     }
 
+    /// Sets the scheme and host the site is deployed to, used by the `absolute_url` filter.
+    pub fn set_base_url<S: Into<String>>(&mut self, base_url: S) -> &mut Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Sets the path the site is mounted under, used by the `relative_url` and `absolute_url` filters.
+    pub fn set_base_path<S: Into<String>>(&mut self, base_path: S) -> &mut Self {
+        self.base_path = base_path.into();
+        self
+    }
+
+    /// Sets the site-wide configuration, available to templates as `site`.
+    pub fn set_site(&mut self, site: Yaml) -> &mut Self {
+        self.site = site;
+        self
+    }
+
+    /// Sets front matter values the page's own front matter is merged over.
+    pub fn set_front_matter_defaults(&mut self, front_matter_defaults: Yaml) -> &mut Self {
+        self.front_matter_defaults = front_matter_defaults;
+        self
+    }
+
+    /// Sets the marker that ends a page's excerpt, e.g. `<!--more-->`. Pages can override it with
+    /// an `excerpt_separator` key in their front matter.
+    pub fn set_excerpt_separator<S: Into<String>>(&mut self, excerpt_separator: S) -> &mut Self {
+        self.excerpt_separator = excerpt_separator.into();
+        self
+    }
+
+    /// Sets the URL the page is served at, relative to the site root, e.g. `/docs/guide.html`.
+    pub fn set_url<S: Into<String>>(&mut self, url: S) -> &mut Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Sets the collection the page belongs to: the top-level directory it's in, `pages` for
+    /// top-level files or `drafts` for drafts.
+    pub fn set_collection<S: Into<String>>(&mut self, collection: S) -> &mut Self {
+        self.collection = collection.into();
+        self
+    }
+
+    /// Sets whether to date pages by their first and last commits in the git repository they're
+    /// in, rather than by the source file's modification time.
+    pub fn set_git_dates(&mut self, git_dates: bool) -> &mut Self {
+        self.git_dates = git_dates;
+        self
+    }
+
+    /// Sets the site's files and their URLs, which the `link` and `post_url` tags look pages up in.
+    pub fn set_page_urls(&mut self, page_urls: Arc<PageUrls>) -> &mut Self {
+        self.page_urls = page_urls;
+        self
+    }
+
+    /// Sets where `parse_file` reads from, the disk unless set.
+    pub fn set_file_system(&mut self, file_system: Arc<dyn FileSystem>) -> &mut Self {
+        self.file_system = file_system;
+        self
+    }
+
+    /// Sets custom Liquid filters, tags, blocks and converters to render pages with.
+    pub fn set_extensions(&mut self, extensions: TemplateExtensions) -> &mut Self {
+        self.extensions = extensions;
+        self
+    }
+
+    pub fn extensions(&self) -> &TemplateExtensions {
+        &self.extensions
+    }
+
     pub fn parse_file(&self) -> Result<Page, io::Error> {
-        let mut file_contents = String::new();
-        File::open(&self.input_file)?.read_to_string(&mut file_contents)?;
-
-        let (front_matter, contents) = if FRONT_MATTER_REGEX.is_match(&file_contents) {
-            let captures = FRONT_MATTER_REGEX.captures(&file_contents).expect("Regex failed despite a match");
-            (YamlLoader::load_from_str(&captures[1]).expect("Could not load YAML")[0].clone(), captures[2].to_string())
-        } else {
-            (Yaml::Null, file_contents)
+        let input_file = Path::new(&self.input_file);
+        let mut page = self.parse_string(self.file_system.read_to_string(input_file)?)?;
+
+        page.last_modified = self.file_system.modified(input_file);
+
+        if self.git_dates && self.file_system.is_on_disk() {
+            page.history = git::file_history(Path::new(&self.input_file));
+        }
+
+        Ok(page)
+    }
+
+    /// Parses a page from source that isn't read from a file. The input file is still used as
+    /// the page's source path and to tell whether it's Markdown, but doesn't need to exist.
+    pub fn parse_string<S: Into<String>>(&self, file_contents: S) -> Result<Page, io::Error> {
+        let file_contents = file_contents.into();
+
+        let (front_matter, contents) = match split_front_matter(&file_contents) {
+            Some(Ok(split)) => split,
+            Some(Err(what)) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: invalid front matter: {}", self.input_file, what)))
+            },
+            None => (Yaml::Null, file_contents)
         };
 
-        let extension = ::std::path::Path::new(&self.input_file).extension().expect("Could not get extension").to_str().unwrap_or("");
+        let front_matter = self.apply_defaults(front_matter);
+        let source_path = if self.source_path.is_empty() { &self.input_file } else { &self.source_path };
+
+        let converter = self.extensions.converter(source_extension(Path::new(&self.input_file)));
 
         Ok(Page {
             front_matter: front_matter,
             contents: contents,
-            extension: extension.to_string(),
+            converter: converter,
             parse_options: self.parse_options,
+            base_url: self.base_url.clone(),
+            base_path: self.base_path.clone(),
+            site: self.site.clone(),
+            excerpt_separator: self.excerpt_separator.clone(),
+            source_path: PathBuf::from(source_path),
+            last_modified: None,
+            history: None,
+            url: self.url.clone(),
+            collection: self.collection.clone(),
+            page_urls: self.page_urls.clone(),
+            extensions: self.extensions.clone(),
+            rendered: Mutex::new(None),
         })
     }
+
+    /// Parses `source` as if it were read from `source_path`, whose extension decides how it's
+    /// rendered. Nothing is read from disk, so the file doesn't need to exist.
+    pub fn parse_source(&self, source_path: &str, source: &str) -> Result<Page, io::Error> {
+        self.clone().set_input_file(source_path).parse_string(source)
+    }
+
+    fn apply_defaults(&self, front_matter: Yaml) -> Yaml {
+        match self.front_matter_defaults {
+            Yaml::Hash(ref defaults) if !defaults.is_empty() => {
+                let mut merged = Yaml::Hash(defaults.clone());
+                if let Yaml::Hash(_) = front_matter {
+                    merge_yaml(&mut merged, front_matter);
+                }
+
+                merged
+            },
+            _ => front_matter
+        }
+    }
 }
 
 impl Default for PageGenerator {
     fn default() -> Self {
         PageGenerator {
             input_file: String::new(),
-            output_file: String::new(),
+            source_path: String::new(),
             parse_options: Options::empty(),
+            base_url: String::new(),
+            base_path: String::new(),
+            site: Yaml::Null,
+            front_matter_defaults: Yaml::Null,
+            excerpt_separator: DEFAULT_EXCERPT_SEPARATOR.to_string(),
+            url: String::new(),
+            collection: String::new(),
+            git_dates: false,
+            page_urls: Arc::new(PageUrls::new()),
+            extensions: TemplateExtensions::new(),
+            file_system: Arc::new(DiskFileSystem),
         }
     }
 }
@@ -77,15 +247,213 @@ impl Default for PageGenerator {
 pub struct Page {
     pub front_matter: Yaml,
     pub contents: String,
-    extension: String,
+    converter: Option<Arc<dyn Converter>>,
     parse_options: Options,
+    base_url: String,
+    base_path: String,
+    site: Yaml,
+    excerpt_separator: String,
+    source_path: PathBuf,
+    last_modified: Option<Timespec>,
+    history: Option<FileHistory>,
+    url: String,
+    collection: String,
+    page_urls: Arc<PageUrls>,
+    extensions: TemplateExtensions,
+    rendered: Mutex<Option<Arc<Rendered>>>,
+}
+
+/// A page's output and excerpt, along with the front matter and contents they were rendered from.
+struct Rendered {
+    front_matter: Yaml,
+    contents: String,
+    html: String,
+    excerpt: String,
 }
 
 impl Page {
     pub fn render_to_string(&self) -> Result<String, io::Error> {
-        let template = ::liquid::parse(&self.contents, Default::default()).expect("Couldn't construct template");
+        self.rendered().map(|rendered| rendered.html.clone())
+    }
+
+    /// Wraps the page's rendered `content` in a layout, which sees the same variables as the page
+    /// along with `content` and the layout's own front matter as `layout`.
+    pub fn render_layout(&self, layout: &Layout, content: &str) -> Result<String, io::Error> {
+        let mut context = self.context();
+        context.set_val("page", self.page_object(Some(&self.rendered()?.excerpt)));
+        context.set_val("content", ::liquid::Value::Str(content.to_string()));
+        if let Some(front_matter) = Self::load_from_yaml(&layout.front_matter) {
+            context.set_val("layout", front_matter);
+        }
+
+        let stash = Stash::new("html");
+        let html = self.render_liquid(&layout.contents, &mut context, &stash)?;
+
+        Ok(stash.restore(&html))
+    }
+
+    /// A short summary of the page as HTML. It's the page's `excerpt` front matter if it has one,
+    /// otherwise everything before the excerpt separator, or failing that the first paragraph.
+    pub fn excerpt(&self) -> Result<String, io::Error> {
+        self.rendered().map(|rendered| rendered.excerpt.clone())
+    }
+
+    /// The excerpt with its markup removed, for meta descriptions and feeds.
+    pub fn excerpt_text(&self) -> Result<String, io::Error> {
+        self.excerpt().map(|html| html_to_text(&html))
+    }
+
+    /// The number of words in the page, not counting markup or Liquid tags.
+    pub fn word_count(&self) -> usize {
+        let contents = LIQUID_MARKUP_REGEX.replace_all(&self.contents, " ");
+        // A converter that fails here fails on the same contents when the page is rendered, which
+        // reports the error, so counting no words in the meantime is fine
+        let html = self.render_markup(&contents, &Stash::new("html")).unwrap_or_default();
+
+        html_to_text(&html).split_whitespace()
+            .filter(|word| word.chars().any(|c| c.is_alphanumeric()))
+            .count()
+    }
+
+    /// Estimated minutes to read the page at `WORDS_PER_MINUTE`, rounded up, and at least one.
+    pub fn reading_time(&self) -> usize {
+        self.word_count().div_ceil(WORDS_PER_MINUTE).max(1)
+    }
+
+    /// The page's path relative to the source directory, e.g. `blog/hello.md`, or the file it was
+    /// read from if it was parsed on its own.
+    pub fn source_path(&self) -> &Path {
+        &self.source_path
+    }
+
+    /// When the source file was last committed, if git dates are on and it has been, otherwise
+    /// when it was last modified, if the filesystem records it.
+    pub fn last_modified(&self) -> Option<Timespec> {
+        self.history.as_ref().map(|history| history.last_modified).or(self.last_modified)
+    }
+
+    /// When the source file was first committed, if git dates are on and it has been.
+    pub fn created(&self) -> Option<Timespec> {
+        self.history.as_ref().map(|history| history.created)
+    }
+
+    /// The source file's git history, if git dates are on and it has been committed.
+    pub fn history(&self) -> Option<&FileHistory> {
+        self.history.as_ref()
+    }
+
+    /// The URL the page is served at, relative to the site root.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The collection the page belongs to, e.g. `blog` for `blog/hello.md`.
+    pub fn collection(&self) -> &str {
+        &self.collection
+    }
+
+    /// The Markdown extensions enabled by the config's `markdown_options`.
+    pub fn markdown_options(&self) -> Options {
+        self.parse_options
+    }
+
+    /// Renders the page's Liquid once and converts it, taking the excerpt from the same output.
+    /// The result is kept until the front matter or contents change, e.g. in a plugin's
+    /// `before_render`.
+    fn rendered(&self) -> Result<Arc<Rendered>, io::Error> {
+        if let Some(ref rendered) = *self.rendered.lock().expect("Rendered page poisoned") {
+            if rendered.front_matter == self.front_matter && rendered.contents == self.contents {
+                return Ok(rendered.clone());
+            }
+        }
+
+        let mut context = self.context();
+        let stash = Stash::new("html");
+
+        // An explicit excerpt is rendered first so the contents can use it. Otherwise the contents
+        // see `page` without an excerpt, since it's taken from their output.
+        context.set_val("page", self.page_object(None));
+        let explicit_excerpt = match self.front_matter["excerpt"].as_str() {
+            Some(excerpt) => {
+                let excerpt = self.render_liquid(excerpt, &mut context, &stash)?;
+                Some(self.render_markup(&excerpt, &stash)?.trim().to_string())
+            },
+            None => None
+        };
+
+        context.set_val("page", self.page_object(explicit_excerpt.as_deref()));
+        let liquid = self.render_liquid(&self.contents, &mut context, &stash)?;
+
+        let excerpt = match explicit_excerpt {
+            Some(excerpt) => excerpt,
+            None => self.render_markup(self.split_excerpt(&liquid), &stash)?.trim().to_string()
+        };
+
+        let rendered = Arc::new(Rendered {
+            front_matter: self.front_matter.clone(),
+            contents: self.contents.clone(),
+            html: self.render_markup(&liquid, &stash)?,
+            excerpt: excerpt,
+        });
+
+        *self.rendered.lock().expect("Rendered page poisoned") = Some(rendered.clone());
+        Ok(rendered)
+    }
+
+    /// The `page` object available to templates: the page's front matter along with its excerpt,
+    /// if it's been rendered, and computed metadata.
+    fn page_object(&self, excerpt: Option<&str>) -> ::liquid::Value {
+        let mut page = match Self::load_from_yaml(&self.front_matter) {
+            Some(::liquid::Value::Object(front_matter)) => front_matter,
+            _ => Default::default()
+        };
+
+        if let Some(excerpt) = excerpt {
+            page.insert("excerpt_text".to_string(), ::liquid::Value::Str(html_to_text(excerpt)));
+            page.insert("excerpt".to_string(), ::liquid::Value::Str(excerpt.to_string()));
+        }
+        page.insert("word_count".to_string(), ::liquid::Value::Num(self.word_count() as f32));
+        page.insert("reading_time".to_string(), ::liquid::Value::Num(self.reading_time() as f32));
+        page.insert("path".to_string(), ::liquid::Value::Str(self.source_path.to_string_lossy().replace('\\', "/")));
+        page.insert("url".to_string(), ::liquid::Value::Str(self.url.clone()));
+        page.insert("collection".to_string(), ::liquid::Value::Str(self.collection.clone()));
+
+        if let Some(last_modified) = self.last_modified() {
+            page.insert("last_modified".to_string(), ::liquid::Value::Str(format_date(last_modified)));
+        }
+
+        if let Some(ref history) = self.history {
+            page.insert("created".to_string(), ::liquid::Value::Str(format_date(history.created)));
+            page.insert("created_by".to_string(), ::liquid::Value::Str(history.created_by.clone()));
+            page.insert("last_modified_by".to_string(), ::liquid::Value::Str(history.last_modified_by.clone()));
+        }
+
+        ::liquid::Value::Object(page)
+    }
 
+    fn split_excerpt<'a>(&self, contents: &'a str) -> &'a str {
+        let separator = self.front_matter["excerpt_separator"].as_str().unwrap_or(&self.excerpt_separator);
+
+        if !separator.is_empty() {
+            if let Some(index) = contents.find(separator) {
+                return &contents[..index];
+            }
+        }
+
+        let contents = contents.trim_start();
+        match contents.find("\n\n").or_else(|| contents.find("\r\n\r\n")) {
+            Some(index) => &contents[..index],
+            None => contents
+        }
+    }
+
+    fn context(&self) -> Context {
         let mut context = Context::new();
+        self.add_filters(&mut context);
+
+        if let Some(site) = Self::load_from_yaml(&self.site) {
+            context.set_val("site", site);
+        }
 
         match self.front_matter.as_hash() {
             Some(hash) => {
@@ -100,23 +468,93 @@ impl Page {
             None => {},
         }
 
-        let mut html = template.render(&mut context).expect("Could not parse").unwrap_or(String::new());
+        context
+    }
+
+    /// Renders Liquid, setting aside output like highlighted code in `stash` so that it's put back
+    /// untouched by `render_markup`.
+    fn render_liquid(&self, source: &str, context: &mut Context, stash: &Stash) -> Result<String, io::Error> {
+        let raw = Stash::new("raw");
+        let source = tags::extract_raw(source, &raw);
+
+        let mut options = LiquidOptions::default();
+        options.tags.insert("link".to_string(), tags::link_tag(self.page_urls.clone(), self.base_path.clone()));
+        options.tags.insert("post_url".to_string(), tags::post_url_tag(self.page_urls.clone(), self.base_path.clone()));
+        options.blocks.insert("highlight".to_string(), tags::highlight_block(raw.clone(), stash.clone()));
+        self.extensions.register_tags(&mut options);
+
+        let template = ::liquid::parse(&source, options).map_err(template_error)?;
+        let rendered = template.render(context).map_err(template_error)?.unwrap_or(String::new());
+
+        Ok(raw.restore(&rendered))
+    }
+
+    fn render_markup(&self, contents: &str, stash: &Stash) -> Result<String, io::Error> {
+        let html = match self.converter {
+            Some(ref converter) => converter.convert(contents, self)?,
+            None => contents.to_string()
+        };
+
+        Ok(stash.restore(&html))
+    }
+
+    /// Whether the page belongs in a build made at `now`. Drafts (`draft: true`) and pages with a
+    /// `date` in the future are left out unless asked for; pages past their `expires` date are
+    /// always left out.
+    pub fn is_published(&self, include_drafts: bool, include_future: bool, now: Timespec) -> Result<bool, io::Error> {
+        if self.is_draft() && !include_drafts {
+            return Ok(false);
+        }
+
+        match self.front_matter_date("date")? {
+            Some(date) if date > now && !include_future => return Ok(false),
+            _ => {}
+        }
 
-        // Parse markdown
-        if self.extension == "md" {
-            html = self.parse_markdown(&html).unwrap_or(String::new());
+        match self.front_matter_date("expires")? {
+            Some(expires) if expires <= now => Ok(false),
+            _ => Ok(true)
         }
+    }
 
-        Ok(html)
+    pub fn is_draft(&self) -> bool {
+        self.front_matter["draft"].as_bool().unwrap_or(false)
     }
 
-    fn parse_markdown(&self, contents: &str) -> Result<String, io::Error> {
-        let parser = Parser::new_ext(contents, self.parse_options);
+    /// A date from the front matter, such as `date` or `last_modified_at`.
+    pub fn front_matter_date(&self, key: &str) -> Result<Option<Timespec>, io::Error> {
+        let invalid = |value: &str| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid {} \"{}\": expected a date like 2017-01-31 or 2017-01-31 09:30:00 +01:00", key, value)
+        );
+
+        match self.front_matter[key] {
+            Yaml::String(ref date) => parse_date(date).map(Some).ok_or_else(|| invalid(date)),
+            Yaml::Null | Yaml::BadValue => Ok(None),
+            ref other => Err(invalid(&format!("{:?}", other)))
+        }
+    }
+
+    fn add_filters(&self, context: &mut Context) {
+        let base_path = self.base_path.clone();
+        context.add_filter("relative_url", Box::new(move |input, args| filters::relative_url(&base_path, input, args)));
+
+        let (base_url, base_path) = (self.base_url.clone(), self.base_path.clone());
+        context.add_filter("absolute_url", Box::new(move |input, args| filters::absolute_url(&base_url, &base_path, input, args)));
 
-        let mut parsed_html = String::with_capacity(contents.len() * 3 / 2);
-        html::push_html(&mut parsed_html, parser);
+        let parse_options = self.parse_options;
+        context.add_filter("markdownify", Box::new(move |input, args| filters::markdownify(parse_options, input, args)));
 
-        Ok(parsed_html)
+        context.add_filter("date", Box::new(filters::date));
+        context.add_filter("slugify", Box::new(filters::slugify));
+        context.add_filter("xml_escape", Box::new(filters::xml_escape));
+        context.add_filter("jsonify", Box::new(filters::jsonify));
+        context.add_filter("where", Box::new(filters::where_filter));
+        context.add_filter("where_exp", Box::new(filters::where_exp));
+        context.add_filter("group_by", Box::new(filters::group_by));
+        context.add_filter("sort", Box::new(filters::sort));
+
+        self.extensions.register_filters(context);
     }
 
     fn load_from_yaml(yaml: &Yaml) -> Option<::liquid::Value> {
@@ -126,11 +564,88 @@ impl Page {
             Yaml::Integer(integer) => Some(::liquid::Value::Num(integer as f32)),
             Yaml::Boolean(bool) => Some(::liquid::Value::Bool(bool)),
             Yaml::Array(ref array) => Some(::liquid::Value::Array(array.iter().filter_map(Self::load_from_yaml).collect())),
+            Yaml::Hash(ref hash) => Some(::liquid::Value::Object(hash.iter()
+                .filter_map(|(key, value)| Some((key.as_str()?.to_string(), Self::load_from_yaml(value)?)))
+                .collect())),
             Yaml::BadValue | Yaml::Null | _ => None,
         }
     }
 }
 
+fn template_error(what: ::liquid::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Couldn't render template: {}", what))
+}
+
+/// Whether `file_contents` starts with front matter that's valid YAML.
+pub fn has_front_matter(file_contents: &str) -> bool {
+    split_front_matter(file_contents).is_some_and(|split| split.is_ok())
+}
+
+/// Splits the front matter `file_contents` starts with, if any, from the rest. Front matter with
+/// nothing but comments in it is `Yaml::Null`.
+fn split_front_matter(file_contents: &str) -> Option<Result<(Yaml, String), ScanError>> {
+    let captures = FRONT_MATTER_REGEX.captures(file_contents)?;
+    let front_matter = YamlLoader::load_from_str(&captures[1])
+        .map(|documents| documents.into_iter().next().unwrap_or(Yaml::Null));
+
+    Some(front_matter.map(|front_matter| (front_matter, captures[2].to_string())))
+}
+
+/// Strips the tags from a fragment of HTML and collapses its whitespace, leaving plain text.
+pub fn html_to_text(html: &str) -> String {
+    let text = HTML_TAG_REGEX.replace_all(html, " ");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Formats a time as an RFC 3339 date in UTC, e.g. `2017-03-01T09:30:00Z`.
+pub fn format_date(time: Timespec) -> String {
+    time::at_utc(time).rfc3339().to_string()
+}
+
+/// Parses a front matter date such as `2017-01-31`, `2017-01-31 09:30` or
+/// `2017-01-31T09:30:00+01:00`. Dates without a UTC offset are taken to be in UTC.
+pub fn parse_date(date: &str) -> Option<Timespec> {
+    let captures = DATE_REGEX.captures(date.trim())?;
+    let number = |index: usize| captures.get(index).map_or(Some(0), |value| value.as_str().parse::<i32>().ok());
+
+    let (year, month, day) = (number(1)?, number(2)?, number(3)?);
+    let (hour, minute, second) = (number(4)?, number(5)?, number(6)?);
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let offset = match captures.get(8) {
+        Some(sign) => {
+            let offset = number(9)? * 3600 + number(10)? * 60;
+            if sign.as_str() == "-" { -offset } else { offset }
+        },
+        None => 0
+    };
+
+    let tm = Tm {
+        tm_sec: second,
+        tm_min: minute,
+        tm_hour: hour,
+        tm_mday: day,
+        tm_mon: month - 1,
+        tm_year: year - 1900,
+        tm_wday: 0,
+        tm_yday: 0,
+        tm_isdst: 0,
+        tm_utcoff: 0,
+        tm_nsec: 0,
+    };
+
+    Some(tm.to_timespec() - time::Duration::seconds(i64::from(offset)))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -138,7 +653,8 @@ mod test {
     use std::io::prelude::Write;
     use std::fs::File;
     use std::env::temp_dir;
-    use std::collections::BTreeMap;
+    use yaml_rust::yaml::Hash;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     use tempdir::TempDir;
 
@@ -146,7 +662,6 @@ mod test {
     fn it_parses_a_valid_markdown_file_to_html() {
         let temp_dir = TempDir::new("parse-valid-markdown").expect("Temp Dir");
         let md_file_name = temp_dir.path().join("test.md");
-        let html_file_name = temp_dir.path().join("test.html");
 
         let mut file = File::create(&md_file_name).expect("Markdown File Create");
 
@@ -154,7 +669,6 @@ mod test {
 
         let actual = PageGenerator::new()
             .set_input_file(md_file_name.to_str().expect("Input File"))
-            .set_output_file(html_file_name.to_str().expect("Output File"))
             .parse_file()
             .expect("Generate Pages");
 
@@ -168,7 +682,6 @@ mod test {
     fn it_parses_frontmatter_and_returns_a_page_object() {
         let temp_dir = TempDir::new("parse-front-matter").expect("Temp Dir");
         let md_file_name = temp_dir.path().join("test.md");
-        let html_file_name = temp_dir.path().join("test.html");
 
         let mut file = File::create(&md_file_name).expect("Markdown file create");
 
@@ -176,17 +689,16 @@ mod test {
 
         let page = PageGenerator::new()
             .set_input_file(md_file_name.to_str().expect("Input File"))
-            .set_output_file(html_file_name.to_str().expect("Output File"))
             .parse_file()
             .expect("Generate Page");
 
-        let mut btree_map = BTreeMap::new();
+        let mut hash = Hash::new();
         let tags = vec![Yaml::String("one".to_string()), Yaml::String("two".to_string())];
 
-        btree_map.insert(Yaml::String("title".to_string()), Yaml::String("My Page".to_string()));
-        btree_map.insert(Yaml::String("tags".to_string()), Yaml::Array(tags));
+        hash.insert(Yaml::String("title".to_string()), Yaml::String("My Page".to_string()));
+        hash.insert(Yaml::String("tags".to_string()), Yaml::Array(tags));
 
-        let expected_frontmatter = Yaml::Hash(btree_map);
+        let expected_frontmatter = Yaml::Hash(hash);
 
         let expected_html = "<h1>This is a test!</h1>".to_string();
 
@@ -198,7 +710,6 @@ mod test {
     fn page_parses_out_as_liquid_template() {
         let temp_dir = TempDir::new("liquid-template-test").expect("Temp Dir");
         let md_file_name = temp_dir.path().join("test.md");
-        let html_file_name = temp_dir.path().join("test.html");
 
         let mut file = File::create(&md_file_name).expect("Markdown file create");
 
@@ -206,7 +717,6 @@ mod test {
 
         let page = PageGenerator::new()
             .set_input_file(md_file_name.to_str().expect("Input file"))
-            .set_output_file(html_file_name.to_str().expect("Output file"))
             .parse_file()
             .expect("Generate page");
 
@@ -219,7 +729,6 @@ mod test {
     fn it_injects_frontmatter_values() {
         let temp_dir = TempDir::new("frontmatter-injection").expect("Temp Dir");
         let md_file_name = temp_dir.path().join("test.md");
-        let html_file_name = temp_dir.path().join("test.html");
 
         let mut file = File::create(&md_file_name).expect("Markdown file create");
 
@@ -244,7 +753,6 @@ This is the 'true' value
 
         let page = PageGenerator::new()
             .set_input_file(md_file_name.to_str().expect("Input file"))
-            .set_output_file(html_file_name.to_str().expect("Output file"))
             .parse_file()
             .expect("Generate Page");
 
@@ -260,7 +768,6 @@ This is the 'true' value
     fn it_injects_complex_frontmatter_values() {
         let temp_dir = TempDir::new("complex-frontmatter-injection").expect("Temp Dir");
         let md_file_name = temp_dir.path().join("test.md");
-        let html_file_name = temp_dir.path().join("test.html");
 
         let mut file = File::create(&md_file_name).expect("Markdown file create");
 
@@ -268,7 +775,6 @@ This is the 'true' value
 
         let page = PageGenerator::new()
             .set_input_file(md_file_name.to_str().expect("Input file"))
-            .set_output_file(html_file_name.to_str().expect("Output file"))
             .parse_file()
             .expect("Generate Page");
 
@@ -277,16 +783,283 @@ This is the 'true' value
         assert_eq!(expected_html, page.render_to_string().expect("Couldn't render").trim());
     }
 
+    #[test]
+    fn it_prefixes_urls_with_the_base_path() {
+        let temp_dir = TempDir::new("base-path-filters").expect("Temp Dir");
+        let md_file_name = temp_dir.path().join("test.md");
+
+        let mut file = File::create(&md_file_name).expect("Markdown file create");
+
+        writeln!(file, "[Home]({{{{ '/' | relative_url }}}}) [Feed]({{{{ 'feed.xml' | absolute_url }}}})").expect("Write markdown");
+
+        let page = PageGenerator::new()
+            .set_input_file(md_file_name.to_str().expect("Input file"))
+            .set_base_url("https://example.com")
+            .set_base_path("/docs")
+            .parse_file()
+            .expect("Generate page");
+
+        let expected_html = "<p><a href=\"/docs/\">Home</a> <a href=\"https://example.com/docs/feed.xml\">Feed</a></p>".to_string();
+
+        assert_eq!(expected_html, page.render_to_string().expect("Couldn't render").trim());
+    }
+
+    #[test]
+    fn it_exposes_the_site_config_to_templates() {
+        let temp_dir = TempDir::new("site-config").expect("Temp Dir");
+        let md_file_name = temp_dir.path().join("test.md");
+
+        let mut file = File::create(&md_file_name).expect("Markdown file create");
+
+        writeln!(file, "{{{{ site.title }}}} ({{{{ site.environment }}}})").expect("Write markdown");
+
+        let site = YamlLoader::load_from_str("title: My Site\nenvironment: production").expect("Site YAML").remove(0);
+        let page = PageGenerator::new()
+            .set_input_file(md_file_name.to_str().expect("Input file"))
+            .set_site(site)
+            .parse_file()
+            .expect("Generate page");
+
+        assert_eq!("<p>My Site (production)</p>", page.render_to_string().expect("Couldn't render").trim());
+    }
+
+    #[test]
+    fn it_provides_jekyll_filters() {
+        let temp_dir = TempDir::new("jekyll-filters").expect("Temp Dir");
+        let md_file_name = temp_dir.path().join("test.html");
+
+        let mut file = File::create(&md_file_name).expect("Markdown file create");
+        writeln!(file, "{{{{ site.members | where: \"team\", \"docs\" | sort: \"name\" | jsonify }}}}|{{{{ \"2017-03-01\" | date: \"%-d %B %Y\" }}}}|{{{{ site.title | slugify }}}}|{{{{ \"*hi*\" | markdownify }}}}")
+            .expect("Write markdown");
+
+        let site = YamlLoader::load_from_str("title: My Site!\nmembers:\n  - {name: Grace, team: docs}\n  - {name: Ada, team: docs}\n  - {name: Alan, team: ops}")
+            .expect("Site YAML").remove(0);
+        let page = PageGenerator::new()
+            .set_input_file(md_file_name.to_str().expect("Input file"))
+            .set_site(site)
+            .parse_file()
+            .expect("Generate page");
+
+        assert_eq!(r#"[{"name":"Ada","team":"docs"},{"name":"Grace","team":"docs"}]|1 March 2017|my-site|<p><em>hi</em></p>"#,
+                   page.render_to_string().expect("Couldn't render").trim());
+    }
+
+    #[test]
+    fn it_merges_front_matter_over_the_defaults() {
+        let temp_dir = TempDir::new("front-matter-defaults").expect("Temp Dir");
+        let md_file_name = temp_dir.path().join("test.md");
+
+        let mut file = File::create(&md_file_name).expect("Markdown file create");
+
+        writeln!(file, "---\nauthor: someone-else\n---\n{{{{ layout }}}} by {{{{ author }}}}").expect("Write markdown");
+
+        let defaults = YamlLoader::load_from_str("layout: doc\nauthor: platform-team").expect("Defaults YAML").remove(0);
+        let page = PageGenerator::new()
+            .set_input_file(md_file_name.to_str().expect("Input file"))
+            .set_front_matter_defaults(defaults)
+            .parse_file()
+            .expect("Generate page");
+
+        assert_eq!(Some("doc"), page.front_matter["layout"].as_str());
+        assert_eq!("<p>doc by someone-else</p>", page.render_to_string().expect("Couldn't render").trim());
+    }
+
+    #[test]
+    fn it_reports_invalid_front_matter() {
+        let error = PageGenerator::new().parse_source("blog/broken.md", "---\ntitle: [unclosed\n---\nBody")
+            .err().expect("Invalid front matter");
+
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert!(error.to_string().starts_with("blog/broken.md: invalid front matter"));
+    }
+
+    #[test]
+    fn it_parses_front_matter_with_only_comments() {
+        let page = PageGenerator::new().parse_source("notes.md", "---\n# Nothing yet\n---\nBody").expect("Parse page");
+
+        assert_eq!(Yaml::Null, page.front_matter);
+        assert_eq!("<p>Body</p>", page.render_to_string().expect("Render").trim());
+        assert!(!has_front_matter("---\ntitle: [unclosed\n---\nBody"));
+    }
+
+    fn page_with_contents(contents: &str) -> Page {
+        let temp_dir = TempDir::new("excerpts").expect("Temp Dir");
+        let md_file_name = temp_dir.path().join("test.md");
+
+        let mut file = File::create(&md_file_name).expect("Markdown file create");
+        write!(file, "{}", contents).expect("Write markdown");
+
+        PageGenerator::new()
+            .set_input_file(md_file_name.to_str().expect("Input file"))
+            .parse_file()
+            .expect("Generate page")
+    }
+
+    #[test]
+    fn it_uses_the_first_paragraph_as_the_excerpt() {
+        let page = page_with_contents("---\nname: World\n---\nHello *{{ name }}* & friends.\n\nThe rest of the post.");
+
+        assert_eq!("<p>Hello <em>World</em> &amp; friends.</p>", page.excerpt().expect("Excerpt"));
+        assert_eq!("Hello World & friends.", page.excerpt_text().expect("Excerpt text"));
+    }
+
+    #[test]
+    fn it_ends_the_excerpt_at_the_separator() {
+        let page = page_with_contents("First paragraph.\n\nSecond paragraph.\n<!--more-->\nThe rest.");
+        assert_eq!("<p>First paragraph.</p>\n<p>Second paragraph.</p>", page.excerpt().expect("Excerpt"));
+
+        let custom = page_with_contents("---\nexcerpt_separator: \"---8<---\"\n---\nOne.\n\nTwo.\n---8<---\nThree.");
+        assert_eq!("One. Two.", custom.excerpt_text().expect("Excerpt text"));
+    }
+
+    #[test]
+    fn it_prefers_an_explicit_excerpt() {
+        let page = page_with_contents("---\nexcerpt: A **short** summary\n---\nThe first paragraph.\n\n{{ page.excerpt_text }}");
+
+        assert_eq!("<p>A <strong>short</strong> summary</p>", page.excerpt().expect("Excerpt"));
+        assert_eq!("<p>The first paragraph.</p>\n<p>A short summary</p>", page.render_to_string().expect("Render").trim());
+    }
+
+    #[test]
+    fn it_renders_the_liquid_once_for_the_page_its_excerpt_and_layouts() {
+        let renders = Arc::new(AtomicUsize::new(0));
+        let counter = renders.clone();
+        let mut extensions = TemplateExtensions::new();
+        extensions.add_filter("counted", move |input, _args| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(input.clone())
+        });
+
+        let page = PageGenerator::new()
+            .set_extensions(extensions)
+            .parse_source("index.md", "---\ntitle: Hello\n---\n{{ page.title | counted }} intro\n<!--more-->\nThe rest.")
+            .expect("Parse page");
+        let layout = Layout { front_matter: Yaml::Null, contents: "{{ page.excerpt_text }}|{{ content }}".to_string() };
+
+        let html = page.render_to_string().expect("Render");
+        assert_eq!("<p>Hello intro</p>", page.excerpt().expect("Excerpt"));
+        assert_eq!("Hello intro|<p>Hello intro</p>\n<!--more-->\nThe rest.", page.render_layout(&layout, &html).expect("Render layout"));
+        assert_eq!(1, renders.load(Ordering::SeqCst));
+
+        let mut page = page;
+        page.contents = "Changed {{ 1 | counted }}".to_string();
+        assert_eq!("<p>Changed 1</p>", page.excerpt().expect("Excerpt"));
+        assert_eq!(2, renders.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn it_computes_page_metadata() {
+        let temp_dir = TempDir::new("metadata").expect("Temp Dir");
+        let md_file_name = temp_dir.path().join("guide.md");
+
+        let mut file = File::create(&md_file_name).expect("Markdown file create");
+        write!(file, "---\ntitle: Guide\n---\n# {{{{ title }}}}\n\nSome *short* words, and {{% if true %}}a tag{{% endif %}}.\n\n{{{{ page.word_count }}}} words, {{{{ page.reading_time }}}} min, at {{{{ page.url }}}}")
+            .expect("Write markdown");
+
+        let page = PageGenerator::new()
+            .set_input_file(md_file_name.to_str().expect("Input file"))
+            .set_url("/docs/guide.html")
+            .parse_file()
+            .expect("Generate page");
+
+        assert_eq!(9, page.word_count());
+        assert_eq!(1, page.reading_time());
+        assert_eq!(md_file_name.as_path(), page.source_path());
+        assert_eq!("/docs/guide.html", page.url());
+        assert!(page.last_modified().expect("Modified time").sec > 0);
+        assert!(page.render_to_string().expect("Render").contains("<p>9 words, 1 min, at /docs/guide.html</p>"));
+    }
+
+    #[test]
+    fn it_falls_back_to_the_modified_time_without_git_history() {
+        let temp_dir = TempDir::new("git-dates").expect("Temp Dir");
+        let md_file_name = temp_dir.path().join("index.md");
+        write!(File::create(&md_file_name).expect("Markdown file create"), "{{{{ page.created }}}}").expect("Write markdown");
+
+        let page = PageGenerator::new()
+            .set_input_file(md_file_name.to_str().expect("Input file"))
+            .set_git_dates(true)
+            .parse_file()
+            .expect("Generate page");
+
+        assert!(page.history().is_none());
+        assert_eq!(None, page.created());
+        assert!(page.last_modified().is_some());
+        assert_eq!("", page.render_to_string().expect("Render").trim());
+    }
+
+    #[test]
+    fn it_estimates_reading_time_from_the_word_count() {
+        let page = page_with_contents(&vec!["word"; WORDS_PER_MINUTE + 1].join(" "));
+
+        assert_eq!(WORDS_PER_MINUTE + 1, page.word_count());
+        assert_eq!(2, page.reading_time());
+    }
+
+    #[test]
+    fn it_formats_dates_in_utc() {
+        assert_eq!("2017-03-01T09:30:00Z", format_date(parse_date("2017-03-01 10:30 +01:00").expect("Date")));
+    }
+
+    #[test]
+    fn it_parses_front_matter_dates() {
+        let midnight = Timespec::new(1485820800, 0);
+
+        assert_eq!(Some(midnight), parse_date("2017-01-31"));
+        assert_eq!(Some(midnight + time::Duration::minutes(570)), parse_date("2017-01-31 09:30"));
+        assert_eq!(Some(midnight + time::Duration::minutes(510)), parse_date("2017-01-31T09:30:00+01:00"));
+        assert_eq!(Some(midnight + time::Duration::minutes(510)), parse_date("2017-01-31 09:30:00 +0100"));
+        assert_eq!(None, parse_date("2017-13-01"));
+        assert_eq!(None, parse_date("31/01/2017"));
+    }
+
+    fn page_with_front_matter(front_matter: &str) -> Page {
+        let temp_dir = TempDir::new("publish-rules").expect("Temp Dir");
+        let md_file_name = temp_dir.path().join("test.md");
+
+        let mut file = File::create(&md_file_name).expect("Markdown file create");
+        writeln!(file, "---\n{}\n---\nContent", front_matter).expect("Write markdown");
+
+        PageGenerator::new()
+            .set_input_file(md_file_name.to_str().expect("Input file"))
+            .parse_file()
+            .expect("Generate page")
+    }
+
+    #[test]
+    fn it_leaves_out_drafts_future_and_expired_pages() {
+        let now = parse_date("2017-06-01").expect("Now");
+
+        let draft = page_with_front_matter("draft: true");
+        assert!(!draft.is_published(false, false, now).unwrap());
+        assert!(draft.is_published(true, false, now).unwrap());
+
+        let future = page_with_front_matter("date: 2017-06-02");
+        assert!(!future.is_published(false, false, now).unwrap());
+        assert!(future.is_published(false, true, now).unwrap());
+
+        let expired = page_with_front_matter("date: 2017-01-01\nexpires: 2017-05-31");
+        assert!(!expired.is_published(true, true, now).unwrap());
+
+        let current = page_with_front_matter("date: 2017-01-01\nexpires: 2017-12-31");
+        assert!(current.is_published(false, false, now).unwrap());
+    }
+
+    #[test]
+    fn it_reports_invalid_publish_dates() {
+        let page = page_with_front_matter("date: next tuesday");
+
+        assert_eq!(io::ErrorKind::InvalidData, page.is_published(false, false, time::get_time()).err().expect("Invalid date").kind());
+    }
+
     #[test]
     #[should_panic]
     fn it_panics_when_file_cannot_be_found() {
         let temp_dir = temp_dir().to_string_lossy().into_owned();
         let md_file_name = temp_dir.clone() + "/test2.md";
-        let html_file_name = temp_dir.clone() + "/test2.html";
 
         let mut page_generator = PageGenerator::new();
-        page_generator.set_input_file(md_file_name.as_str())
-            .set_output_file(html_file_name.as_str());
+        page_generator.set_input_file(md_file_name.as_str());
 
         page_generator.parse_file().expect("Generate Pages");
     }
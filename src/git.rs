@@ -0,0 +1,114 @@
+use std::path::Path;
+use std::process::Command;
+
+use time::Timespec;
+
+/// When a file was first and last committed, and by whom.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileHistory {
+    pub created: Timespec,
+    pub created_by: String,
+    pub last_modified: Timespec,
+    pub last_modified_by: String,
+}
+
+/// Reads a file's history from the git repository it's in, following renames. Returns `None` if
+/// git isn't installed, the file isn't in a repository or it has never been committed.
+pub fn file_history(path: &Path) -> Option<FileHistory> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new(".")
+    };
+
+    let output = Command::new("git")
+        .arg("-C").arg(directory)
+        .args(["log", "--follow", "--format=%at%x09%an", "--"])
+        .arg(path.file_name()?)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let commits: Vec<(Timespec, String)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_commit)
+        .collect();
+
+    // git log lists the newest commit first
+    let (last_modified, last_modified_by) = commits.first()?.clone();
+    let (created, created_by) = commits.last()?.clone();
+
+    Some(FileHistory {
+        created: created,
+        created_by: created_by,
+        last_modified: last_modified,
+        last_modified_by: last_modified_by,
+    })
+}
+
+fn parse_commit(line: &str) -> Option<(Timespec, String)> {
+    let mut fields = line.splitn(2, '\t');
+    let timestamp = fields.next()?.parse::<i64>().ok()?;
+    let author = fields.next()?.to_string();
+
+    Some((Timespec::new(timestamp, 0), author))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    fn git(directory: &Path, args: &[&str], author: &str, date: &str) {
+        let status = Command::new("git")
+            .current_dir(directory)
+            .args(args)
+            .env("GIT_AUTHOR_NAME", author)
+            .env("GIT_AUTHOR_EMAIL", "author@example.com")
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_NAME", author)
+            .env("GIT_COMMITTER_EMAIL", "author@example.com")
+            .env("GIT_COMMITTER_DATE", date)
+            .status()
+            .expect("Run git");
+
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn it_reads_the_first_and_last_commits() {
+        let temp_dir = TempDir::new("git-history").expect("Temp Dir");
+        let pages = temp_dir.path().join("pages");
+        ::std::fs::create_dir(&pages).expect("Pages dir");
+        let page = pages.join("index.md");
+
+        git(temp_dir.path(), &["init", "-q"], "Ada", "2017-01-01T00:00:00Z");
+
+        write!(File::create(&page).expect("Create page"), "First").expect("Write page");
+        git(temp_dir.path(), &["add", "."], "Ada", "2017-01-01T00:00:00Z");
+        git(temp_dir.path(), &["commit", "-q", "-m", "Add page"], "Ada", "2017-01-01T00:00:00Z");
+
+        write!(File::create(&page).expect("Create page"), "Second").expect("Write page");
+        git(temp_dir.path(), &["commit", "-q", "-a", "-m", "Edit page"], "Grace", "2017-02-01T12:00:00Z");
+
+        let history = file_history(&page).expect("History");
+
+        assert_eq!(Timespec::new(1483228800, 0), history.created);
+        assert_eq!("Ada", history.created_by);
+        assert_eq!(Timespec::new(1485950400, 0), history.last_modified);
+        assert_eq!("Grace", history.last_modified_by);
+    }
+
+    #[test]
+    fn it_has_no_history_outside_a_repository() {
+        let temp_dir = TempDir::new("no-git-history").expect("Temp Dir");
+        let page = temp_dir.path().join("index.md");
+        File::create(&page).expect("Create page");
+
+        assert_eq!(None, file_history(&page));
+    }
+}
@@ -4,6 +4,14 @@ extern crate hyper;
 extern crate walkdir;
 extern crate regex;
 extern crate liquid;
+extern crate percent_encoding;
+extern crate time;
+extern crate ctrlc;
+extern crate strsim;
+extern crate toml;
+extern crate serde_json;
+extern crate glob;
+extern crate rhai;
 #[macro_use] extern crate lazy_static;
 
 #[cfg(test)]
@@ -11,4 +19,14 @@ extern crate tempdir;
 
 pub mod commands;
 pub mod config;
+pub mod converters;
+pub mod extensions;
+pub mod external;
+pub mod filters;
 pub mod generation;
+pub mod git;
+pub mod plugins;
+pub mod scripts;
+pub mod site;
+pub mod tags;
+pub mod vfs;
@@ -3,11 +3,12 @@ extern crate aluminum;
 
 use std::io;
 use std::io::prelude::*;
-use std::error::Error;
-use std::fs::File;
-use clap::{App, Arg, AppSettings, SubCommand};
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use clap::{App, Arg, ArgMatches, AppSettings, SubCommand};
 use aluminum::commands;
-use aluminum::config::Config;
+use aluminum::config::{Config, ConfigFormat, CONFIG_FILE_NAME, CONFIG_FILE_NAMES, DEFAULT_ENVIRONMENT};
 
 const VERSION_NUMBER: &'static str = "0.3.0";
 
@@ -16,74 +17,211 @@ fn main() {
         .setting(AppSettings::ArgRequiredElseHelp)
         .version(VERSION_NUMBER)
         .about("Static site generator")
-        .subcommand(SubCommand::with_name("new").arg(Arg::with_name("project name")
-                                                         .index(1)
-                                                         .required(true)))
-        .subcommand(SubCommand::with_name("build"))
+        .arg(Arg::with_name("config")
+                 .long("config")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .global(true)
+                 .help("Config file to use instead of _config.yml; later files override earlier ones"))
+        .arg(Arg::with_name("root")
+                 .long("root")
+                 .takes_value(true)
+                 .global(true)
+                 .help("Project root directory; defaults to the nearest directory containing a config file"))
+        .arg(Arg::with_name("env")
+                 .long("env")
+                 .takes_value(true)
+                 .global(true)
+                 .help("Environment to build for, overlaying _config.<env>.yml; defaults to $ALUMINUM_ENV or development"))
+        .arg(Arg::with_name("set")
+                 .long("set")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .global(true)
+                 .validator(|assignment| Config::parse_override(&assignment).map(|_| ()))
+                 .help("Override a config key, e.g. --set output=public; takes precedence over ALUMINUM_<KEY> variables"))
+        .subcommand(SubCommand::with_name("new")
+                        .arg(Arg::with_name("project name")
+                                 .index(1)
+                                 .required(true))
+                        .arg(Arg::with_name("config-format")
+                                 .long("config-format")
+                                 .takes_value(true)
+                                 .possible_values(&["yaml", "toml"])
+                                 .default_value("yaml")
+                                 .help("Format to write the new project's config file in")))
+        .subcommand(SubCommand::with_name("build")
+                        .arg(drafts_arg())
+                        .arg(future_arg()))
         .subcommand(SubCommand::with_name("clean"))
-        .subcommand(SubCommand::with_name("serve"))
+        .subcommand(SubCommand::with_name("render")
+                        .about("Print one page's rendered HTML, wrapped in its layouts")
+                        .arg(Arg::with_name("file")
+                                 .index(1)
+                                 .required(true)
+                                 .help("Markdown file in the source directory to render")))
+        .subcommand(SubCommand::with_name("config").about("Print the effective configuration"))
+        .subcommand(SubCommand::with_name("serve")
+                        .arg(drafts_arg())
+                        .arg(future_arg())
+                        .arg(Arg::with_name("host")
+                                 .long("host")
+                                 .takes_value(true)
+                                 .help("Address to bind the server to"))
+                        .arg(Arg::with_name("port")
+                                 .long("port")
+                                 .takes_value(true)
+                                 .validator(|port| Config::validate_port(&port).map(|_| ()))
+                                 .help("Port to bind the server to")))
         .get_matches();
 
+    match matches.subcommand() {
+        ("new", Some(new)) => {
+            let project_name = new.value_of("project name").unwrap_or("");
+            let config_format = new.value_of("config-format").unwrap_or("yaml").parse::<ConfigFormat>().expect("Format was validated by clap");
 
-    if let ("new", Some(new)) = matches.subcommand() {
-        let project_name = if new.is_present("project name") {
-            new.value_of("project name").unwrap()
-        } else {
-            ""
-        };
-
-        commands::new_project(project_name).unwrap();
-    } else if matches.is_present("build") {
-        let mut config_file_contents = String::new();
-        let mut config_file = match File::open("_config.yml") {
-            Ok(file) => file,
-            Err(what) => panic!("No config file present: {}", Error::description(&what))
-        };
-
-        match config_file.read_to_string(&mut config_file_contents) {
-            Ok(_) => {},
-            Err(what) => panic!("Unable to read config file: {}", Error::description(&what))
-        }
+            commands::new_project(project_name, config_format).unwrap();
+        },
+        ("build", Some(build)) => {
+            let mut config = load_config(build);
+            include_unpublished(&mut config, build);
+
+            println!("Building project...");
+            exit_on_error(commands::build_project(&config));
+        },
+        ("clean", Some(clean)) => {
+            let config = load_config(clean);
 
-        println!("Building project...");
-        let config = Config::from_string(config_file_contents);
-        match commands::build_project(&config) {
-            Ok(_) => {},
-            Err(what) => {
-                writeln!(io::stderr(), "Error: {}", Error::description(&what)).expect("Print Error");
+            println!("Cleaning project...");
+            match commands::clean_project(&config) {
+                Ok(_) | Err(_) => {},
             }
-        }
-    } else if matches.is_present("clean") {
-        let mut config_file_contents = String::new();
-        let mut config_file = match File::open("_config.yml") {
-            Ok(file) => file,
-            Err(what) => panic!("No config file present: {}", Error::description(&what))
-        };
-
-        match config_file.read_to_string(&mut config_file_contents) {
-            Ok(_) => {},
-            Err(what) => panic!("Unable to read config file: {}", Error::description(&what))
-        }
+        },
+        ("render", Some(render)) => {
+            // Relative to where aluminum was run from, before switching into the project root
+            let file = env::current_dir().map(|working_dir| working_dir.join(render.value_of("file").unwrap_or("")));
+            let config = load_config(render);
+
+            exit_on_error(file.and_then(|file| commands::render_file(&config, &file)).map(|html| print!("{}", html)));
+        },
+        ("config", Some(config)) => {
+            print!("{}", load_config(config).to_yaml_string());
+            println!();
+        },
+        ("serve", Some(serve)) => {
+            let mut config = load_config(serve);
+            include_unpublished(&mut config, serve);
+
+            if let Some(host) = serve.value_of("host") {
+                config.host = host.to_string();
+            }
+
+            if let Some(port) = serve.value_of("port") {
+                config.port = Config::validate_port(port).expect("Port was validated by clap");
+            }
+
+            println!("Serving project...");
+            exit_on_error(commands::serve(&config));
+        },
+        _ => {}
+    }
+}
+
+fn drafts_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("drafts")
+        .long("drafts")
+        .help("Include drafts: pages marked draft: true and anything under _drafts")
+}
+
+fn future_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("future")
+        .long("future")
+        .help("Include pages dated in the future")
+}
+
+/// Applies `--drafts` and `--future`, which can only switch these on, never off.
+fn include_unpublished(config: &mut Config, matches: &ArgMatches) {
+    config.drafts = config.drafts || matches.is_present("drafts");
+    config.future = config.future || matches.is_present("future");
+}
 
-        println!("Cleaning project...");
-        let config = Config::from_string(config_file_contents);
-        match commands::clean_project(&config) {
-            Ok(_) | Err(_) => {},
+/// Loads the configuration for a subcommand, exiting with an error message if it can't be found
+/// or read.
+fn load_config(matches: &ArgMatches) -> Config {
+    match find_config(matches) {
+        Ok(config) => config,
+        Err(what) => {
+            writeln!(io::stderr(), "Error: {}", what).expect("Print Error");
+            process::exit(1);
         }
-    } else if matches.is_present("serve") {
-        let mut config_file_contents = String::new();
-        let mut config_file = match File::open("_config.yml") {
-            Ok(file) => file,
-            Err(what) => panic!("No config file present: {}", Error::description(&what))
-        };
-
-        match config_file.read_to_string(&mut config_file_contents) {
-            Ok(_) => {},
-            Err(what) => panic!("Unable to read config file: {}", Error::description(&what))
+    }
+}
+
+/// Works out which config files to read and switches into the project root, so relative paths in
+/// the config resolve against it.
+///
+/// Files passed with `--config` are relative to the directory aluminum was run from. Without
+/// them, `_config.yml`, `_config.toml` or `aluminum.toml` is read from `--root` if given, or otherwise from the nearest directory
+/// containing one, starting at the current directory and walking up. Each file is followed by its
+/// overlay for the selected environment, if there is one. `ALUMINUM_<KEY>` variables are applied
+/// on top, followed by `--set` flags.
+fn find_config(matches: &ArgMatches) -> Result<Config, io::Error> {
+    let working_dir = env::current_dir()?;
+    let mut config_files: Vec<PathBuf> = matches.values_of("config")
+        .map(|paths| paths.map(|path| working_dir.join(path)).collect())
+        .unwrap_or_default();
+
+    let project_root = match matches.value_of("root") {
+        Some(root) => working_dir.join(root),
+        None if !config_files.is_empty() => working_dir.clone(),
+        None => {
+            let config_file = Config::find_config_file(&working_dir).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound,
+                               format!("No config file ({}) found in {} or any of its parent directories",
+                                       CONFIG_FILE_NAMES.join(", "), working_dir.display()))
+            })?;
+            let project_root = config_file.parent().map(|parent| parent.to_path_buf()).unwrap_or_else(|| working_dir.clone());
+
+            config_files.push(config_file);
+            project_root
         }
+    };
+
+    if config_files.is_empty() {
+        config_files.push(Config::config_file_in(&project_root).unwrap_or_else(|| project_root.join(CONFIG_FILE_NAME)));
+    }
+
+    env::set_current_dir(&project_root).map_err(|what| {
+        io::Error::new(what.kind(), format!("Couldn't change to project root {}: {}", project_root.display(), what))
+    })?;
+
+    let environment = matches.value_of("env").map(|environment| environment.to_string())
+        .or_else(|| env::var("ALUMINUM_ENV").ok())
+        .unwrap_or_else(|| DEFAULT_ENVIRONMENT.to_string());
+
+    let mut yaml = Config::load_yaml_for_environment(&config_files, &environment)?;
+
+    let variables = env::vars_os().filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+    let mut overrides = Config::environment_overrides(variables, &yaml);
+
+    if let Some(assignments) = matches.values_of("set") {
+        overrides.extend(assignments.map(|assignment| Config::parse_override(assignment).expect("Override was validated by clap")));
+    }
+
+    for (key, value) in overrides {
+        Config::apply_override(&mut yaml, &key, &value);
+    }
+
+    let mut config = Config::from_yaml(&yaml)?;
+    config.root_dir = project_root.to_string_lossy().into_owned();
+    Ok(config)
+}
 
-        println!("Serving project");
-        let config = Config::from_string(config_file_contents);
-        commands::serve(&config).expect("Serve");
+fn exit_on_error(result: Result<(), io::Error>) {
+    if let Err(what) = result {
+        writeln!(io::stderr(), "Error: {}", what).expect("Print Error");
+        process::exit(1);
     }
 }
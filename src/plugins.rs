@@ -0,0 +1,182 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use time::{self, Timespec};
+
+use super::config::Config;
+use super::filters;
+use super::generation::{format_date, Page};
+use super::site::Site;
+
+/// How many of the most recent pages the feed lists.
+pub const FEED_LIMIT: usize = 10;
+
+/// Hooks into `build_project`. Every hook does nothing by default, so a plugin only implements
+/// the ones it needs. An error from any hook stops the build.
+pub trait Plugin {
+    /// Names the plugin in error messages.
+    fn name(&self) -> &str;
+
+    /// Called once the configuration is loaded, before the source directory is read.
+    fn after_config(&mut self, _config: &mut Config) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    /// Called once every page has been read, to add generated pages to the site.
+    fn generate(&mut self, _site: &mut Site) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    /// Called before each page is rendered, including generated ones.
+    fn before_render(&mut self, _page: &mut Page) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    /// Called with each page's HTML before it's written.
+    fn after_render(&mut self, _page: &Page, _html: &mut String) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    /// Called once every page has been written to the output directory.
+    fn after_build(&mut self, _site: &Site, _output_dir: &Path) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+/// Names the plugin an error came from.
+pub(crate) fn plugin_error(plugin: &dyn Plugin, what: io::Error) -> io::Error {
+    io::Error::new(what.kind(), format!("Plugin `{}` failed: {}", plugin.name(), what))
+}
+
+/// The plugins named in the config's `plugins` list.
+pub fn built_in_plugins(config: &Config) -> Result<Vec<Box<dyn Plugin>>, io::Error> {
+    config.plugins.iter()
+        .map(|name| match name.as_str() {
+            "sitemap" => Ok(Box::new(SitemapPlugin) as Box<dyn Plugin>),
+            "feed" => Ok(Box::new(FeedPlugin) as Box<dyn Plugin>),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                    format!("Unknown plugin `{}`; the built-in plugins are sitemap and feed", name)))
+        })
+        .collect()
+}
+
+/// Writes `sitemap.xml`, listing every page except those with `sitemap: false` in their front
+/// matter.
+pub struct SitemapPlugin;
+
+impl Plugin for SitemapPlugin {
+    fn name(&self) -> &str {
+        "sitemap"
+    }
+
+    fn after_build(&mut self, site: &Site, output_dir: &Path) -> Result<(), io::Error> {
+        let mut pages: Vec<&Page> = site.pages.iter()
+            .filter(|page| page.front_matter["sitemap"].as_bool() != Some(false))
+            .collect();
+        pages.sort_by(|a, b| a.url().cmp(b.url()));
+
+        let mut sitemap = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                                        <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+
+        for page in pages {
+            sitemap.push_str(&format!("  <url>\n    <loc>{}</loc>\n", filters::escape_xml(&site.absolute_url(page.url()))));
+            if let Some(last_modified) = last_modified(page)? {
+                sitemap.push_str(&format!("    <lastmod>{}</lastmod>\n", format_date(last_modified)));
+            }
+            sitemap.push_str("  </url>\n");
+        }
+
+        sitemap.push_str("</urlset>\n");
+
+        fs::write(output_dir.join("sitemap.xml"), sitemap)
+    }
+}
+
+/// Writes an Atom feed, `feed.xml`, of the most recent pages with a `date`. Each entry's content
+/// is the page's HTML before it's wrapped in its layouts.
+pub struct FeedPlugin;
+
+impl Plugin for FeedPlugin {
+    fn name(&self) -> &str {
+        "feed"
+    }
+
+    fn after_build(&mut self, site: &Site, output_dir: &Path) -> Result<(), io::Error> {
+        let mut entries = Vec::new();
+        for page in &site.pages {
+            if let Some(date) = page.front_matter_date("date")? {
+                let updated = last_modified(page)?.unwrap_or(date);
+                entries.push((date, updated, page));
+            }
+        }
+
+        entries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.2.url().cmp(b.2.url())));
+        entries.truncate(FEED_LIMIT);
+
+        let updated = entries.iter().map(|&(_, updated, _)| updated).max().unwrap_or_else(time::get_time);
+        let title = site.config.site["title"].as_str().unwrap_or("Feed");
+        let home = site.absolute_url("/");
+
+        let mut feed = format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                                <feed xmlns=\"http://www.w3.org/2005/Atom\">\n  \
+                                <title>{}</title>\n  \
+                                <link href=\"{}\" rel=\"self\"/>\n  \
+                                <link href=\"{}\"/>\n  \
+                                <updated>{}</updated>\n  \
+                                <id>{}</id>\n",
+                               filters::escape_xml(title), filters::escape_xml(&site.absolute_url("/feed.xml")),
+                               filters::escape_xml(&home), format_date(updated), filters::escape_xml(&home));
+
+        for (published, updated, page) in entries {
+            let url = filters::escape_xml(&site.absolute_url(page.url()));
+            let title = page.front_matter["title"].as_str().unwrap_or(page.url());
+            let html = page.render_to_string()?;
+
+            feed.push_str(&format!("  <entry>\n    \
+                                    <title>{}</title>\n    \
+                                    <link href=\"{}\"/>\n    \
+                                    <id>{}</id>\n    \
+                                    <published>{}</published>\n    \
+                                    <updated>{}</updated>\n    \
+                                    <summary>{}</summary>\n    \
+                                    <content type=\"html\">{}</content>\n  \
+                                    </entry>\n",
+                                   filters::escape_xml(title), url, url, format_date(published), format_date(updated),
+                                   filters::escape_xml(&page.excerpt_text()?), filters::escape_xml(html.trim())));
+        }
+
+        feed.push_str("</feed>\n");
+
+        fs::write(output_dir.join("feed.xml"), feed)
+    }
+}
+
+/// When a page last changed: its `last_modified_at` front matter, its last commit if git dates
+/// are on, its `date`, or failing those, its file's modification time.
+fn last_modified(page: &Page) -> Result<Option<Timespec>, io::Error> {
+    if let Some(last_modified_at) = page.front_matter_date("last_modified_at")? {
+        return Ok(Some(last_modified_at));
+    }
+
+    if let Some(history) = page.history() {
+        return Ok(Some(history.last_modified));
+    }
+
+    Ok(page.front_matter_date("date")?.or_else(|| page.last_modified()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_picks_the_built_in_plugins_named_in_the_config() {
+        let config = Config::from_string("plugins: [feed, sitemap]".to_string()).expect("Parse config");
+        let names: Vec<_> = built_in_plugins(&config).expect("Plugins").iter().map(|plugin| plugin.name().to_string()).collect();
+        assert_eq!(vec!["feed", "sitemap"], names);
+
+        let config = Config::from_string("plugins: [comments]".to_string()).expect("Parse config");
+        assert!(built_in_plugins(&config).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use liquid::{FilterError, Value};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST, FLOAT, INT};
+use rhai::module_resolvers::DummyModuleResolver;
+use yaml_rust::yaml::{Hash, Yaml};
+
+use super::extensions::TemplateExtensions;
+use super::generation::Page;
+use super::plugins::Plugin;
+use super::vfs::FileSystem;
+
+/// The directory under the source directory that scripts are read from.
+pub const SCRIPTS_DIR: &'static str = "_scripts";
+
+/// Functions named `filter_<name>` become Liquid filters.
+const FILTER_PREFIX: &'static str = "filter_";
+
+/// How many operations a single call into a script may run before it's stopped, so a script
+/// stuck in a loop can't hang the build.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// Rhai scripts from the `_scripts` directory, which tweak pages without a full plugin. Each
+/// `.rhai` file can define any of:
+///
+/// - `fn filter_shout(input, args)`: a Liquid filter, `shout`, given its input and an array of
+///   its arguments.
+/// - `fn transform_front_matter(front_matter, page)`: returns a page's new front matter before
+///   it's rendered.
+/// - `fn transform_html(html, page)`: returns a page's new HTML once it's rendered.
+///
+/// `page` is a map of the page's source `path`, `url` and `content`. Scripts can't import modules
+/// or touch the filesystem, and are stopped if they run for too long. Their `print` and `debug`
+/// output goes to stderr, so it never ends up in a rendered page.
+pub struct Scripts {
+    engine: Arc<Engine>,
+    scripts: Vec<Script>,
+}
+
+struct Script {
+    path: PathBuf,
+    ast: Arc<AST>,
+}
+
+impl Scripts {
+    /// Compiles every `.rhai` file directly in `directory` on `file_system`, in name order. A
+    /// missing directory has no scripts.
+    pub fn load(file_system: &dyn FileSystem, directory: &Path) -> Result<Self, io::Error> {
+        let engine = sandboxed_engine();
+        let mut scripts = Vec::new();
+
+        let mut paths: Vec<PathBuf> = file_system.files(directory).into_iter()
+            .filter(|path| path.parent() == Some(directory) && path.extension().is_some_and(|extension| extension == "rhai"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let source = file_system.read_to_string(&path)?;
+            let ast = engine.compile(&source)
+                .map_err(|what| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), what)))?;
+
+            scripts.push(Script { path: path, ast: Arc::new(ast) });
+        }
+
+        Ok(Scripts {
+            engine: Arc::new(engine),
+            scripts: scripts,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    /// Adds each script's `filter_` functions to `extensions`. A filter defined by several
+    /// scripts comes from the last of them.
+    pub fn add_filters(&self, extensions: &mut TemplateExtensions) {
+        for script in &self.scripts {
+            let functions: Vec<String> = script.ast.iter_functions()
+                .filter(|function| function.name.starts_with(FILTER_PREFIX) && function.params.len() == 2)
+                .map(|function| function.name.to_string())
+                .collect();
+
+            for function in functions {
+                let (engine, ast, path) = (self.engine.clone(), script.ast.clone(), script.path.clone());
+                let filter_name = function[FILTER_PREFIX.len()..].to_string();
+
+                extensions.add_filter(&filter_name, move |input, args| {
+                    let args: Array = args.iter().map(value_to_dynamic).collect();
+
+                    engine.call_fn::<Dynamic>(&mut Scope::new(), &ast, &function, (value_to_dynamic(input), args))
+                        .map(dynamic_to_value)
+                        .map_err(|what| FilterError::InvalidType(format!("{}: {}", path.display(), what)))
+                });
+            }
+        }
+    }
+
+    /// Whether any script defines `function`, taking a value and the page.
+    fn defines(&self, function: &str) -> bool {
+        self.scripts.iter().any(|script| script.defines(function))
+    }
+
+    /// Runs `function` from every script that defines it, in order, each given the result of the
+    /// one before.
+    fn transform(&self, function: &str, mut value: Dynamic, page: &Page) -> Result<Dynamic, io::Error> {
+        for script in self.scripts.iter().filter(|script| script.defines(function)) {
+            value = self.engine.call_fn::<Dynamic>(&mut Scope::new(), &script.ast, function, (value, page_map(page)))
+                .map_err(|what| script_error(&script.path, &what))?;
+        }
+
+        Ok(value)
+    }
+}
+
+impl Script {
+    fn defines(&self, function: &str) -> bool {
+        self.ast.iter_functions().any(|defined| defined.name == function && defined.params.len() == 2)
+    }
+}
+
+impl Plugin for Scripts {
+    fn name(&self) -> &str {
+        "scripts"
+    }
+
+    fn before_render(&mut self, page: &mut Page) -> Result<(), io::Error> {
+        if !self.defines("transform_front_matter") {
+            return Ok(());
+        }
+
+        let front_matter = match page.front_matter {
+            Yaml::Hash(_) => yaml_to_dynamic(&page.front_matter),
+            _ => Dynamic::from_map(Map::new())
+        };
+
+        let front_matter = self.transform("transform_front_matter", front_matter, page)?;
+        if !front_matter.is_map() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("transform_front_matter should return a map, not {}", front_matter.type_name())));
+        }
+
+        page.front_matter = dynamic_to_yaml(front_matter);
+        Ok(())
+    }
+
+    fn after_render(&mut self, page: &Page, html: &mut String) -> Result<(), io::Error> {
+        if !self.defines("transform_html") {
+            return Ok(());
+        }
+
+        let transformed = self.transform("transform_html", Dynamic::from(html.clone()), page)?;
+
+        *html = transformed.into_string()
+            .map_err(|type_name| io::Error::new(io::ErrorKind::InvalidData, format!("transform_html should return a string, not {}", type_name)))?;
+        Ok(())
+    }
+}
+
+/// An engine with Rhai's standard functions, which don't include any file access, and no way to
+/// import other scripts.
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new())
+        .set_max_operations(MAX_OPERATIONS)
+        .on_print(|text| {
+            writeln!(io::stderr(), "{}", text).ok();
+        })
+        .on_debug(|text, source, position| {
+            match source {
+                Some(source) => writeln!(io::stderr(), "{} @ {:?} | {}", source, position, text),
+                None => writeln!(io::stderr(), "{:?} | {}", position, text)
+            }.ok();
+        });
+
+    engine
+}
+
+fn script_error(path: &Path, what: &EvalAltResult) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), what))
+}
+
+fn page_map(page: &Page) -> Map {
+    let mut map = Map::new();
+    map.insert("path".into(), Dynamic::from(page.source_path().to_string_lossy().into_owned()));
+    map.insert("url".into(), Dynamic::from(page.url().to_string()));
+    map.insert("content".into(), Dynamic::from(page.contents.clone()));
+
+    map
+}
+
+fn value_to_dynamic(value: &Value) -> Dynamic {
+    match *value {
+        Value::Num(number) if number.fract() == 0.0 => Dynamic::from(number as INT),
+        Value::Num(number) => Dynamic::from(number as FLOAT),
+        Value::Str(ref string) => Dynamic::from(string.clone()),
+        Value::Bool(boolean) => Dynamic::from(boolean),
+        Value::Array(ref items) => Dynamic::from_array(items.iter().map(value_to_dynamic).collect()),
+        Value::Object(ref object) => Dynamic::from_map(object.iter()
+            .map(|(key, value)| (key.as_str().into(), value_to_dynamic(value)))
+            .collect())
+    }
+}
+
+/// Liquid has no empty value, so `()` becomes an empty string.
+fn dynamic_to_value(dynamic: Dynamic) -> Value {
+    if dynamic.is_array() {
+        let items = dynamic.into_array().expect("Checked array");
+        Value::Array(items.into_iter().map(dynamic_to_value).collect())
+    } else if dynamic.is_map() {
+        let map = dynamic.cast::<Map>();
+        Value::Object(map.into_iter().map(|(key, value)| (key.to_string(), dynamic_to_value(value))).collect())
+    } else if let Ok(integer) = dynamic.as_int() {
+        Value::Num(integer as f32)
+    } else if let Ok(float) = dynamic.as_float() {
+        Value::Num(float as f32)
+    } else if let Ok(boolean) = dynamic.as_bool() {
+        Value::Bool(boolean)
+    } else if dynamic.is_unit() {
+        Value::str("")
+    } else {
+        Value::Str(dynamic.to_string())
+    }
+}
+
+fn yaml_to_dynamic(yaml: &Yaml) -> Dynamic {
+    match *yaml {
+        Yaml::Integer(integer) => Dynamic::from(integer),
+        Yaml::Real(ref real) => real.parse::<FLOAT>().map(Dynamic::from).unwrap_or_else(|_| Dynamic::from(real.clone())),
+        Yaml::String(ref string) => Dynamic::from(string.clone()),
+        Yaml::Boolean(boolean) => Dynamic::from(boolean),
+        Yaml::Array(ref items) => Dynamic::from_array(items.iter().map(yaml_to_dynamic).collect()),
+        Yaml::Hash(ref hash) => Dynamic::from_map(hash.iter()
+            .filter_map(|(key, value)| key.as_str().map(|key| (key.into(), yaml_to_dynamic(value))))
+            .collect()),
+        Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => Dynamic::UNIT
+    }
+}
+
+fn dynamic_to_yaml(dynamic: Dynamic) -> Yaml {
+    if dynamic.is_array() {
+        let items = dynamic.into_array().expect("Checked array");
+        Yaml::Array(items.into_iter().map(dynamic_to_yaml).collect())
+    } else if dynamic.is_map() {
+        let map = dynamic.cast::<Map>();
+        let hash: Hash = map.into_iter().map(|(key, value)| (Yaml::String(key.to_string()), dynamic_to_yaml(value))).collect();
+        Yaml::Hash(hash)
+    } else if let Ok(integer) = dynamic.as_int() {
+        Yaml::Integer(integer)
+    } else if let Ok(float) = dynamic.as_float() {
+        Yaml::Real(float.to_string())
+    } else if let Ok(boolean) = dynamic.as_bool() {
+        Yaml::Boolean(boolean)
+    } else if dynamic.is_unit() {
+        Yaml::Null
+    } else {
+        Yaml::String(dynamic.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generation::PageGenerator;
+    use liquid::Renderable;
+    use vfs::MemoryFileSystem;
+
+    fn scripts(sources: &[(&str, &str)]) -> Result<Scripts, io::Error> {
+        let mut file_system = MemoryFileSystem::new();
+        for &(name, source) in sources {
+            file_system.insert(Path::new("pages/_scripts").join(name), source);
+        }
+
+        Scripts::load(&file_system, Path::new("pages/_scripts"))
+    }
+
+    fn page() -> Page {
+        PageGenerator::new().set_url("/index.html").parse_string("---\ntitle: Home\ntags: [a, b]\n---\nHello").expect("Parse page")
+    }
+
+    #[test]
+    fn it_transforms_pages_in_script_order() {
+        let mut scripts = scripts(&[
+            ("b.rhai", "fn transform_html(html, page) { html + \"b\" }"),
+            ("a.rhai", "fn transform_html(html, page) { html + \"a\" }\n\
+                        fn transform_front_matter(front_matter, page) { front_matter.tags.push(page.content); front_matter }"),
+            ("notes.txt", "not a script"),
+            ("vendor/c.rhai", "fn transform_html(html, page) { html + \"c\" }")
+        ]).expect("Load scripts");
+
+        let mut page = page();
+        scripts.before_render(&mut page).expect("Transform front matter");
+        assert_eq!(3, page.front_matter["tags"].as_vec().expect("Tags").len());
+        assert_eq!(Some("Hello"), page.front_matter["tags"][2].as_str());
+        assert_eq!(Some("Home"), page.front_matter["title"].as_str());
+
+        let mut html = "<p>Hello</p>".to_string();
+        scripts.after_render(&page, &mut html).expect("Transform HTML");
+        assert_eq!("<p>Hello</p>ab", html);
+    }
+
+    #[test]
+    fn it_registers_filters() {
+        let scripts = scripts(&[("filters.rhai", "fn filter_double(input, args) { input * 2 }")]).expect("Load scripts");
+        let mut extensions = TemplateExtensions::new();
+        scripts.add_filters(&mut extensions);
+
+        let template = ::liquid::parse("{{ 21 | double }}", Default::default()).expect("Parse template");
+        let mut context = ::liquid::Context::new();
+        extensions.register_filters(&mut context);
+
+        assert_eq!("42", template.render(&mut context).expect("Render").expect("Output"));
+    }
+
+    #[test]
+    fn it_sandboxes_scripts() {
+        let mut page = page();
+
+        let mut importing = scripts(&[("import.rhai", "fn transform_html(html, page) { import \"../secrets\" as secrets; html }")])
+            .expect("Load scripts");
+        assert!(importing.after_render(&page, &mut String::new()).is_err());
+
+        let mut spinning = scripts(&[("spin.rhai", "fn transform_front_matter(front_matter, page) { loop {} }")]).expect("Load scripts");
+        let error = spinning.before_render(&mut page).expect_err("Stop the loop");
+        assert!(error.to_string().contains("spin.rhai"));
+
+        let error = scripts(&[("broken.rhai", "fn transform_html(html, page) {")]).err().expect("Broken script");
+        assert!(error.to_string().contains("broken.rhai"));
+    }
+}
@@ -0,0 +1,609 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use liquid::Value;
+use pulldown_cmark::{Options, OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
+use time;
+use yaml_rust::YamlLoader;
+use yaml_rust::yaml::{Hash, Yaml};
+
+use super::config::{merge_yaml, Config};
+use super::converters::{source_extension, Converter};
+use super::extensions::TemplateExtensions;
+use super::external::parse_json;
+use super::filters;
+use super::generation::{has_front_matter, Page, PageGenerator};
+use super::plugins::{plugin_error, Plugin};
+use super::tags::PageUrls;
+use super::vfs::{DiskFileSystem, FileSystem};
+
+/// Directory under the source directory holding unfinished pages, only built with `--drafts`.
+const DRAFTS_DIR: &'static str = "_drafts";
+
+/// Directory under the source directory holding the templates pages are wrapped in.
+pub const LAYOUTS_DIR: &'static str = "_layouts";
+
+/// Directory under the source directory holding data files, available to templates as
+/// `site.data`.
+pub const DATA_DIR: &'static str = "_data";
+
+/// What a build writes: each file's path relative to the output directory, and its contents.
+pub type SiteOutput = BTreeMap<String, Vec<u8>>;
+
+/// Every file in the source directory, with the converter it's built with if it's a page.
+type SourceFiles = Vec<(PathBuf, Option<Arc<dyn Converter>>)>;
+
+/// A template from `_layouts` that pages name with `layout` in their front matter. The page's
+/// HTML is available to it as `content`, and a layout can name another layout to be wrapped in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub front_matter: Yaml,
+    pub contents: String,
+}
+
+/// A file copied into the site as it is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Asset {
+    pub source: PathBuf,
+    /// Where it's written, relative to the output directory.
+    pub output_path: String,
+}
+
+/// Everything a build is made from, read into memory: the pages, in the order they were read,
+/// layouts, data files and assets.
+pub struct Site {
+    pub config: Config,
+    pub pages: Vec<Page>,
+    /// Layouts by file name, without the extension.
+    pub layouts: HashMap<String, Layout>,
+    /// The contents of `_data`, keyed by path without extensions, e.g. `team/members.yml` is
+    /// `data["team"]["members"]`.
+    pub data: Yaml,
+    pub assets: Vec<Asset>,
+    page_generator: PageGenerator,
+    file_system: Arc<dyn FileSystem>,
+}
+
+impl Site {
+    /// A site with no pages yet. `page_generator` holds the site-wide settings generated pages are
+    /// parsed with.
+    pub fn new(config: Config, page_generator: PageGenerator) -> Self {
+        Site {
+            config: config,
+            pages: Vec::new(),
+            layouts: HashMap::new(),
+            data: Yaml::Hash(Hash::new()),
+            assets: Vec::new(),
+            page_generator: page_generator,
+            file_system: Arc::new(DiskFileSystem),
+        }
+    }
+
+    /// Reads the site in the config's source directory. Only pages that would be published are
+    /// kept, as in `build_project`.
+    pub fn load(config: &Config) -> Result<Self, io::Error> {
+        Self::load_with_extensions(config, &TemplateExtensions::new())
+    }
+
+    /// Reads the site like `load`, rendering its pages with custom Liquid filters, tags and blocks.
+    pub fn load_with_extensions(config: &Config, extensions: &TemplateExtensions) -> Result<Self, io::Error> {
+        Self::load_from_file_system(config, extensions, Arc::new(DiskFileSystem))
+    }
+
+    /// Reads the site like `load_with_extensions`, from `file_system` rather than the disk.
+    pub fn load_from_file_system(config: &Config, extensions: &TemplateExtensions, file_system: Arc<dyn FileSystem>) -> Result<Self, io::Error> {
+        let (mut site, files) = Self::load_files(config, extensions, file_system)?;
+        let pages_path = Path::new(&config.source_dir);
+        let mut page_generator = site.page_generator.clone();
+        let now = time::get_time();
+
+        for (file, converter) in files {
+            let source_path = file.strip_prefix(pages_path).unwrap();
+            let (relative_path, in_drafts) = match site_path(source_path, config.drafts) {
+                Some(site_path) => site_path,
+                None => continue
+            };
+
+            if let Some(converter) = converter {
+                let collection = page_type(relative_path, in_drafts);
+                let page = page_generator.set_input_file(file.to_str().expect("Couldn't convert for some reason"))
+                    .set_source_path(path_to_url(source_path))
+                    .set_url(page_url(relative_path, converter.output_extension()))
+                    .set_collection(collection.as_str())
+                    .set_front_matter_defaults(config.front_matter_defaults(source_path, &collection))
+                    .parse_file()?;
+
+                let published = page.is_published(config.drafts, config.future, now)
+                    .map_err(|what| io::Error::new(what.kind(), format!("{}: {}", file.display(), what)))?;
+
+                if published {
+                    site.pages.push(page);
+                }
+            } else {
+                site.assets.push(Asset {
+                    output_path: file_name(&file),
+                    source: file,
+                });
+            }
+        }
+
+        Ok(site)
+    }
+
+    /// Reads the site's layouts and data from `file_system` and finds the URL every file will be
+    /// built at, without parsing any pages, e.g. to render one page with `render_source`. The
+    /// site has no pages or assets.
+    pub fn load_without_pages(config: &Config, extensions: &TemplateExtensions, file_system: Arc<dyn FileSystem>) -> Result<Self, io::Error> {
+        Self::load_files(config, extensions, file_system).map(|(site, _)| site)
+    }
+
+    /// Loads the site without its pages or assets, along with the files they're read from.
+    fn load_files(config: &Config, extensions: &TemplateExtensions, file_system: Arc<dyn FileSystem>) -> Result<(Self, SourceFiles), io::Error> {
+        let pages_path = Path::new(&config.source_dir);
+
+        let layouts = load_layouts(file_system.as_ref(), &pages_path.join(LAYOUTS_DIR))?;
+        let data = load_data(file_system.as_ref(), &pages_path.join(DATA_DIR))?;
+
+        let mut site_variables = config.site.clone();
+        if data.as_hash().is_some_and(|data| !data.is_empty()) {
+            let mut with_data = Hash::new();
+            with_data.insert(Yaml::String("data".to_string()), data.clone());
+            merge_yaml(&mut site_variables, Yaml::Hash(with_data));
+        }
+
+        let files: SourceFiles = file_system.files(pages_path).into_iter()
+            .map(|file| {
+                let converter = page_converter(extensions, file_system.as_ref(), &file);
+                (file, converter)
+            })
+            .collect();
+
+        // Every file that will be built, so the link tags can check their targets exist
+        let page_urls: PageUrls = files.iter()
+            .filter_map(|(file, converter)| {
+                let source_path = file.strip_prefix(pages_path).unwrap();
+                let (relative_path, _) = site_path(source_path, config.drafts)?;
+                let url = match converter {
+                    Some(converter) => page_url(relative_path, converter.output_extension()),
+                    None => format!("/{}", file_name(file))
+                };
+
+                Some((path_to_url(source_path), url))
+            })
+            .collect();
+
+        let mut page_generator = PageGenerator::new();
+        page_generator.set_parse_options(markdown_options(config))
+            .set_base_url(config.base_url.as_str())
+            .set_base_path(config.base_path.as_str())
+            .set_site(site_variables)
+            .set_excerpt_separator(config.excerpt_separator.as_str())
+            .set_git_dates(config.git_dates)
+            .set_page_urls(Arc::new(page_urls))
+            .set_extensions(extensions.clone())
+            .set_file_system(file_system.clone());
+
+        let mut site = Site::new(config.clone(), page_generator);
+        site.layouts = layouts;
+        site.data = data;
+        site.file_system = file_system;
+
+        Ok((site, files))
+    }
+
+    /// Adds a page generated from `source`, built as if it were a file at `source_path` in the
+    /// source directory, e.g. `tags/rust.md` is written to `tags/rust.html`. Files no converter
+    /// handles, like `feed.xml`, keep their extension.
+    pub fn add_page(&mut self, source_path: &str, source: &str) -> Result<(), io::Error> {
+        let page = self.parse_page(source_path, source)?;
+        self.pages.push(page);
+        Ok(())
+    }
+
+    /// Parses `source` as the page at `source_path` in the source directory, with the site's
+    /// settings and front matter defaults, without adding it to the site. Pages under `_drafts`
+    /// get the URL they'd be built at with drafts included. Paths that are absolute or contain
+    /// `..` are rejected, since the page would be written outside the output directory.
+    pub fn parse_page(&self, source_path: &str, source: &str) -> Result<Page, io::Error> {
+        let path = Path::new(source_path);
+        if !is_inside(path) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't inside the source directory", source_path)));
+        }
+
+        let (relative_path, in_drafts) = site_path(path, true).unwrap_or((path, false));
+        let collection = page_type(relative_path, in_drafts);
+        let output_extension = self.page_generator.extensions().converter(source_extension(path))
+            .map(|converter| converter.output_extension().to_string())
+            .unwrap_or_else(|| source_extension(path).to_string());
+
+        self.page_generator.clone()
+            .set_url(page_url(relative_path, &output_extension))
+            .set_collection(collection.as_str())
+            .set_front_matter_defaults(self.config.front_matter_defaults(path, &collection))
+            .parse_source(source_path, source)
+    }
+
+    /// Renders `source` as the page at `source_path` in the source directory, wrapped in its
+    /// layouts, e.g. to preview a file that's being edited.
+    pub fn render_source(&self, source_path: &str, source: &str) -> Result<String, io::Error> {
+        self.render_page(&self.parse_page(source_path, source)?)
+    }
+
+    /// The page served at `url`, relative to the site root, e.g. `/blog/hello.html`.
+    pub fn page(&self, url: &str) -> Option<&Page> {
+        self.pages.iter().find(|page| page.url() == url)
+    }
+
+    /// The pages in a collection, e.g. `blog` for the pages under `blog/`.
+    pub fn collection(&self, name: &str) -> Vec<&Page> {
+        self.pages.iter().filter(|page| page.collection() == name).collect()
+    }
+
+    /// The pages with `tag` in their `tags` front matter, which is a list or a space-separated
+    /// string.
+    pub fn tagged(&self, tag: &str) -> Vec<&Page> {
+        self.pages.iter()
+            .filter(|page| match page.front_matter["tags"] {
+                Yaml::Array(ref tags) => tags.iter().any(|candidate| candidate.as_str() == Some(tag)),
+                Yaml::String(ref tags) => tags.split_whitespace().any(|candidate| candidate == tag),
+                _ => false
+            })
+            .collect()
+    }
+
+    /// Renders a page and wraps it in its layouts.
+    pub fn render_page(&self, page: &Page) -> Result<String, io::Error> {
+        let html = page.render_to_string()?;
+        self.apply_layouts(page, html)
+    }
+
+    /// Renders every page and reads every asset, without writing anything.
+    pub fn render(&self) -> Result<SiteOutput, io::Error> {
+        let mut output = SiteOutput::new();
+
+        for page in &self.pages {
+            output.insert(output_path(page), self.render_page(page)?.into_bytes());
+        }
+
+        self.read_assets(&mut output)?;
+        Ok(output)
+    }
+
+    /// Renders the site like `render`, running each plugin's `before_render` and `after_render`
+    /// hooks around every page.
+    pub fn render_with_plugins(&mut self, plugins: &mut [Box<dyn Plugin>]) -> Result<SiteOutput, io::Error> {
+        let mut output = SiteOutput::new();
+
+        for index in 0..self.pages.len() {
+            for plugin in plugins.iter_mut() {
+                plugin.before_render(&mut self.pages[index]).map_err(|what| plugin_error(plugin.as_ref(), what))?;
+            }
+
+            let page = &self.pages[index];
+            let mut html = self.render_page(page)?;
+
+            for plugin in plugins.iter_mut() {
+                plugin.after_render(page, &mut html).map_err(|what| plugin_error(plugin.as_ref(), what))?;
+            }
+
+            output.insert(output_path(page), html.into_bytes());
+        }
+
+        self.read_assets(&mut output)?;
+        Ok(output)
+    }
+
+    /// Writes rendered output to the config's output directory. Fails without writing anything if
+    /// a path is absolute or contains `..`.
+    pub fn write(&self, output: &SiteOutput) -> Result<(), io::Error> {
+        if let Some(path) = output.keys().find(|path| !is_inside(Path::new(path))) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't inside the output directory", path)));
+        }
+
+        let output_dir = Path::new(&self.config.output_dir);
+        fs::create_dir_all(output_dir)?;
+
+        for (path, contents) in output {
+            let destination = output_dir.join(path);
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            fs::write(destination, contents)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn absolute_url(&self, url: &str) -> String {
+        filters::absolute_url(&self.config.base_url, &self.config.base_path, &Value::str(url), &[])
+            .map(|url| url.to_string())
+            .unwrap_or_else(|_| url.to_string())
+    }
+
+    /// Wraps `html` in the layout the page names, then the layout that one names, and so on.
+    /// Layouts that don't exist are skipped.
+    fn apply_layouts(&self, page: &Page, mut html: String) -> Result<String, io::Error> {
+        let mut name = page.front_matter["layout"].as_str();
+        let mut applied = Vec::new();
+
+        while let Some(current) = name {
+            let layout = match self.layouts.get(current) {
+                Some(layout) => layout,
+                None => break
+            };
+
+            if applied.contains(&current) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("{}: layout `{}` wraps itself", page.source_path().display(), current)));
+            }
+            applied.push(current);
+
+            html = page.render_layout(layout, &html)?;
+            name = layout.front_matter["layout"].as_str();
+        }
+
+        Ok(html)
+    }
+
+    fn read_assets(&self, output: &mut SiteOutput) -> Result<(), io::Error> {
+        for asset in &self.assets {
+            output.insert(asset.output_path.clone(), self.file_system.read(&asset.source)?);
+        }
+
+        Ok(())
+    }
+}
+
+fn markdown_options(config: &Config) -> Options {
+    let mut markdown_options = Options::empty();
+
+    if config.markdown_options.contains(&"footnotes".to_string()) {
+        markdown_options.insert(OPTION_ENABLE_FOOTNOTES);
+    }
+
+    if config.markdown_options.contains(&"tables".to_string()) {
+        markdown_options.insert(OPTION_ENABLE_TABLES);
+    }
+
+    markdown_options
+}
+
+/// Reads every file in `directory` as a layout, named after the file without its extension.
+fn load_layouts(file_system: &dyn FileSystem, directory: &Path) -> Result<HashMap<String, Layout>, io::Error> {
+    let mut layouts = HashMap::new();
+
+    for path in file_system.files(directory) {
+        if path.parent() != Some(directory) {
+            continue;
+        }
+
+        let name = match path.file_stem() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => continue
+        };
+
+        // Parsed like a page to split off its front matter
+        let page = PageGenerator::new().set_input_file(path.to_str().unwrap_or("")).parse_string(file_system.read_to_string(&path)?)?;
+        layouts.insert(name, Layout { front_matter: page.front_matter, contents: page.contents });
+    }
+
+    Ok(layouts)
+}
+
+/// Reads the YAML and JSON files under `directory` into a mapping, nested by directory.
+fn load_data(file_system: &dyn FileSystem, directory: &Path) -> Result<Yaml, io::Error> {
+    let mut data = Yaml::Hash(Hash::new());
+
+    for file in file_system.files(directory) {
+        let invalid = |what: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file.display(), what));
+
+        let value = match file.extension().and_then(|extension| extension.to_str()) {
+            Some("yml") | Some("yaml") => YamlLoader::load_from_str(&file_system.read_to_string(&file)?)
+                .map_err(|what| invalid(what.to_string()))?
+                .into_iter().next().unwrap_or(Yaml::Null),
+            Some("json") => parse_json(&file_system.read_to_string(&file)?).map_err(invalid)?,
+            _ => continue
+        };
+
+        // Nest the value under each directory and the file's name, e.g. team/members.yml
+        let relative_path = file.strip_prefix(directory).unwrap().with_extension("");
+        let nested = relative_path.components().rev().fold(value, |value, part| {
+            let mut hash = Hash::new();
+            hash.insert(Yaml::String(part.as_os_str().to_string_lossy().into_owned()), value);
+            Yaml::Hash(hash)
+        });
+
+        merge_yaml(&mut data, nested);
+    }
+
+    Ok(data)
+}
+
+/// The converter that builds `file` into a page, or `None` if it's copied as an asset. Files
+/// whose converter requires front matter are assets unless they start with some.
+fn page_converter(extensions: &TemplateExtensions, file_system: &dyn FileSystem, file: &Path) -> Option<Arc<dyn Converter>> {
+    let converter = extensions.converter(source_extension(file))?;
+    if converter.requires_front_matter() {
+        let contents = file_system.read(file).ok()?;
+        if !String::from_utf8(contents).is_ok_and(|contents| has_front_matter(&contents)) {
+            return None;
+        }
+    }
+
+    Some(converter)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|file_name| file_name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+fn output_path(page: &Page) -> String {
+    page.url().trim_start_matches('/').to_string()
+}
+
+/// Where a file in the source directory ends up in the site, and whether it's a draft. Drafts are
+/// built as if they lived directly in the source directory. Returns `None` for files that aren't
+/// built: drafts unless `include_drafts` is set, and anything under a directory starting with `_`.
+fn site_path(source_path: &Path, include_drafts: bool) -> Option<(&Path, bool)> {
+    let (relative_path, in_drafts) = match source_path.strip_prefix(DRAFTS_DIR) {
+        Ok(draft_path) => (draft_path, true),
+        Err(_) => (source_path, false)
+    };
+
+    if (in_drafts && !include_drafts) || relative_path.components().any(|part| part.as_os_str().to_string_lossy().starts_with('_')) {
+        return None;
+    }
+
+    Some((relative_path, in_drafts))
+}
+
+/// The URL a page is served at, relative to the site root, given the extension its converter
+/// writes.
+fn page_url(relative_path: &Path, output_extension: &str) -> String {
+    format!("/{}", path_to_url(&relative_path.with_extension(output_extension)))
+}
+
+/// Whether a relative path stays inside the directory it's relative to: it isn't absolute and has
+/// no `..` components.
+fn is_inside(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Joins a relative path's components with `/`, whatever the platform's separator.
+fn path_to_url(path: &Path) -> String {
+    let parts: Vec<_> = path.components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+
+    parts.join("/")
+}
+
+/// The type a page is matched against by front matter defaults, and its collection: `drafts` for
+/// drafts, otherwise the top-level directory it's in under the source directory, or `pages` for
+/// top-level files.
+fn page_type(relative_path: &Path, in_drafts: bool) -> String {
+    if in_drafts {
+        return "drafts".to_string();
+    }
+
+    let mut components = relative_path.components();
+    match (components.next(), components.next()) {
+        (Some(directory), Some(_)) => directory.as_os_str().to_string_lossy().into_owned(),
+        _ => "pages".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site() -> Site {
+        let mut config = Config::from_string("title: Aluminum".to_string()).expect("Parse config");
+        config.source_dir = "tests/fixtures/layouts-and-data".to_string();
+        config.output_dir = "/nonexistent/output".to_string();
+
+        Site::load(&config).expect("Load site")
+    }
+
+    #[test]
+    fn it_loads_pages_layouts_data_and_assets() {
+        let site = site();
+
+        let mut layouts: Vec<_> = site.layouts.keys().map(|name| name.as_str()).collect();
+        layouts.sort();
+
+        assert_eq!(2, site.pages.len());
+        assert_eq!(vec!["default", "post"], layouts);
+        assert_eq!(Path::new("blog/hello.md"), site.page("/blog/hello.html").expect("Page").source_path());
+        assert_eq!(Some("default"), site.layouts["post"].front_matter["layout"].as_str());
+        assert_eq!(Some("Grace"), site.data["team"][1]["name"].as_str());
+        assert_eq!(Some("/blog/hello.html"), site.data["nav"]["main"][1]["url"].as_str());
+        assert_eq!(vec![Asset {
+            source: PathBuf::from("tests/fixtures/layouts-and-data/style.css"),
+            output_path: "style.css".to_string()
+        }], site.assets);
+    }
+
+    #[test]
+    fn it_finds_pages_by_url_collection_and_tag() {
+        let site = site();
+
+        assert_eq!(Some("Home"), site.page("/index.html").and_then(|page| page.front_matter["title"].as_str()));
+        assert!(site.page("/missing.html").is_none());
+
+        let urls = |pages: Vec<&Page>| pages.iter().map(|page| page.url().to_string()).collect::<Vec<_>>();
+        assert_eq!(vec!["/blog/hello.html"], urls(site.collection("blog")));
+        assert_eq!(vec!["/index.html"], urls(site.collection("pages")));
+        assert_eq!(vec!["/blog/hello.html"], urls(site.tagged("rust")));
+        assert!(site.tagged("python").is_empty());
+    }
+
+    #[test]
+    fn it_renders_to_memory() {
+        let output = site().render().expect("Render site");
+
+        assert_eq!(vec!["blog/hello.html", "index.html", "style.css"], output.keys().collect::<Vec<_>>());
+        assert!(String::from_utf8_lossy(&output["blog/hello.html"]).contains("<article class=\"post\">"));
+        assert_eq!(b"body { margin: 0; }\n".to_vec(), output["style.css"]);
+    }
+
+    #[test]
+    fn it_adds_generated_pages_to_the_site() {
+        let config = Config::from_string("defaults:\n  - scope:\n      path: tags\n    values:\n      layout: tag".to_string())
+            .expect("Parse config");
+        let mut site = Site::new(config, PageGenerator::new());
+
+        site.add_page("tags/rust.md", "---\ntitle: Rust\n---\nPosts tagged *{{ title }}* in {{ layout }}").expect("Add page");
+
+        let page = &site.pages[0];
+        assert_eq!("/tags/rust.html", page.url());
+        assert_eq!("tags", page.collection());
+        assert_eq!(None, page.last_modified());
+        assert_eq!("<p>Posts tagged <em>Rust</em> in tag</p>", page.render_to_string().expect("Render").trim());
+    }
+
+    #[test]
+    fn it_renders_source_that_isnt_in_the_site() {
+        let site = site();
+        let html = site.render_source("_drafts/blog/unsaved.md", "---\ntitle: Unsaved\nlayout: post\n---\nIn *{{ page.collection }}*")
+            .expect("Render source");
+
+        assert!(html.contains("<title>Unsaved | Aluminum</title>"));
+        assert!(html.contains("<p>In <em>drafts</em></p>"));
+        assert_eq!("/blog/unsaved.html", site.parse_page("_drafts/blog/unsaved.md", "").expect("Parse page").url());
+        assert!(site.page("/blog/unsaved.html").is_none());
+    }
+
+    #[test]
+    fn it_keeps_generated_pages_inside_the_output_directory() {
+        let mut site = Site::new(Config::default(), PageGenerator::new());
+
+        for path in &["../../x.md", "blog/../../x.md", "/etc/x.md"] {
+            let error = site.add_page(path, "Escaped").expect_err("Path outside the source directory");
+            assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+        }
+        assert!(site.pages.is_empty());
+
+        let mut output = SiteOutput::new();
+        output.insert("../x.html".to_string(), b"Escaped".to_vec());
+        let error = site.write(&output).expect_err("Path outside the output directory");
+        assert!(error.to_string().contains("../x.html"));
+    }
+
+    #[test]
+    fn it_stops_layouts_that_wrap_themselves() {
+        let mut site = Site::new(Config::default(), PageGenerator::new());
+        site.layouts.insert("loop".to_string(), Layout {
+            front_matter: YamlLoader::load_from_str("layout: loop").expect("Parse YAML").remove(0),
+            contents: "[{{ content }}]".to_string()
+        });
+        site.add_page("index.md", "---\nlayout: loop\n---\nHi").expect("Add page");
+
+        let error = site.render_page(&site.pages[0]).expect_err("Layout loop");
+        assert!(error.to_string().contains("layout `loop` wraps itself"));
+    }
+}
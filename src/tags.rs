@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use liquid::{Block, Context, Error, Renderable, Tag, Token, Value};
+use regex::{Captures, Regex};
+
+use super::filters;
+
+/// Maps each file in the site, by its path relative to the source directory, to the URL it's
+/// served at relative to the site root.
+pub type PageUrls = HashMap<String, String>;
+
+lazy_static! {
+    static ref RAW_REGEX: Regex = Regex::new(r"(?s)\{%-?\s*raw\s*-?%\}(.*?)\{%-?\s*endraw\s*-?%\}").unwrap();
+}
+
+/// Output set aside while a page is rendered and put back afterwards, so that later stages like
+/// Liquid or Markdown leave it alone.
+#[derive(Clone)]
+pub struct Stash {
+    name: String,
+    entries: Arc<Mutex<Vec<String>>>,
+}
+
+impl Stash {
+    pub fn new(name: &str) -> Self {
+        Stash {
+            name: name.to_string(),
+            entries: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Sets some output aside and returns the placeholder that stands in for it.
+    pub fn insert(&self, output: String) -> String {
+        let mut entries = self.entries.lock().expect("Stash lock");
+        entries.push(output);
+
+        self.placeholder(entries.len() - 1)
+    }
+
+    /// Swaps the placeholders in `rendered` back for their output. A placeholder Markdown has
+    /// wrapped in a paragraph of its own is unwrapped.
+    pub fn restore(&self, rendered: &str) -> String {
+        let entries = self.entries.lock().expect("Stash lock");
+
+        entries.iter().enumerate().fold(rendered.to_string(), |restored, (index, output)| {
+            let placeholder = self.placeholder(index);
+
+            restored.replace(&format!("<p>{}</p>", placeholder), output).replace(&placeholder, output)
+        })
+    }
+
+    fn placeholder(&self, index: usize) -> String {
+        format!("\u{FFFC}{}-{}\u{FFFC}", self.name, index)
+    }
+}
+
+/// Sets aside the contents of each `{% raw %}` block, so Liquid outputs them as they are.
+pub fn extract_raw(source: &str, stash: &Stash) -> String {
+    RAW_REGEX.replace_all(source, |captures: &Captures| stash.insert(captures[1].to_string())).into_owned()
+}
+
+/// `{% link docs/intro.md %}`: the URL of the page built from a source file. Fails if there's no
+/// such file.
+pub fn link_tag(page_urls: Arc<PageUrls>, base_path: String) -> Box<Tag> {
+    Box::new(move |_tag_name, arguments, _options| {
+        let path = argument("link", arguments)?;
+        let url = page_urls.get(path.trim_start_matches('/'))
+            .ok_or_else(|| Error::Other(format!("link: there's no `{}` in the source directory", path)))?;
+
+        site_url(&base_path, url)
+    })
+}
+
+/// `{% post_url 2017-08-01-hello %}`: the URL of the page whose source file has that name, or
+/// that path relative to the source directory, minus the extension.
+pub fn post_url_tag(page_urls: Arc<PageUrls>, base_path: String) -> Box<Tag> {
+    Box::new(move |_tag_name, arguments, _options| {
+        let name = argument("post_url", arguments)?;
+
+        let mut matching: Vec<_> = page_urls.iter()
+            .filter(|&(path, _)| {
+                let path = Path::new(path);
+                let without_extension = path.with_extension("");
+
+                path.extension().is_some_and(|extension| extension == "md") &&
+                    (without_extension == Path::new(&name) || path.file_stem().is_some_and(|stem| stem == name.as_str()))
+            })
+            .collect();
+        matching.sort();
+
+        match matching.len() {
+            0 => Err(Error::Other(format!("post_url: there's no post named `{}`", name))),
+            1 => site_url(&base_path, matching[0].1),
+            _ => {
+                let paths: Vec<_> = matching.iter().map(|&(path, _)| path.as_str()).collect();
+                Err(Error::Other(format!("post_url: `{}` could be any of {}", name, paths.join(", "))))
+            }
+        }
+    })
+}
+
+/// `{% highlight rust %}...{% endhighlight %}`: a code block, marked with its language for
+/// syntax highlighting. Add `linenos` after the language to number the lines.
+pub fn highlight_block(raw: Stash, html: Stash) -> Box<Block> {
+    Box::new(move |_tag_name, arguments, elements, options| {
+        let (language, line_numbers) = match arguments {
+            [ref language] => (language.to_string(), false),
+            [ref language, Token::Identifier(ref option)] if option == "linenos" => (language.to_string(), true),
+            _ => return Err(Error::Other("highlight expects a language, optionally followed by `linenos`".to_string()))
+        };
+
+        Ok(Box::new(Highlight {
+            language: language,
+            line_numbers: line_numbers,
+            body: ::liquid::parser::parse(elements, options)?,
+            raw: raw.clone(),
+            html: html.clone(),
+        }))
+    })
+}
+
+struct Highlight {
+    language: String,
+    line_numbers: bool,
+    body: Vec<Box<dyn Renderable>>,
+    raw: Stash,
+    html: Stash,
+}
+
+impl Renderable for Highlight {
+    fn render(&self, context: &mut Context) -> Result<Option<String>, Error> {
+        let mut code = String::new();
+        for element in &self.body {
+            if let Some(output) = element.render(context)? {
+                code.push_str(&output);
+            }
+        }
+
+        let code = self.raw.restore(&code);
+        let code = escape_html(code.trim_start_matches(&['\r', '\n'][..]).trim_end());
+
+        let code = if self.line_numbers {
+            code.lines().enumerate()
+                .map(|(index, line)| format!("<span class=\"lineno\">{}</span>{}", index + 1, line))
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            code
+        };
+
+        let language = escape_html(&self.language);
+        let html = format!("<figure class=\"highlight\"><pre><code class=\"language-{0}\" data-lang=\"{0}\">{1}</code></pre></figure>",
+                           language, code);
+
+        Ok(Some(self.html.insert(html)))
+    }
+}
+
+struct Text(String);
+
+impl Renderable for Text {
+    fn render(&self, _context: &mut Context) -> Result<Option<String>, Error> {
+        Ok(Some(self.0.clone()))
+    }
+}
+
+/// The argument to a tag like `link`. Paths aren't Liquid values, so the lexer may have split
+/// them into several tokens.
+fn argument(tag_name: &str, arguments: &[Token]) -> Result<String, Error> {
+    let argument: String = arguments.iter().map(|token| token.to_string()).collect();
+
+    if argument.is_empty() {
+        return Err(Error::Other(format!("{} expects a path", tag_name)));
+    }
+
+    Ok(argument)
+}
+
+fn site_url(base_path: &str, url: &str) -> Result<Box<dyn Renderable>, Error> {
+    let url = filters::relative_url(base_path, &Value::str(url), &[])?;
+
+    Ok(Box::new(Text(url.to_string())))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use liquid::LiquidOptions;
+
+    fn render(source: &str) -> Result<String, Error> {
+        let mut page_urls = PageUrls::new();
+        page_urls.insert("docs/intro.md".to_string(), "/docs/intro.html".to_string());
+        page_urls.insert("blog/2017-08-01-hello.md".to_string(), "/blog/2017-08-01-hello.html".to_string());
+        page_urls.insert("css/site.css".to_string(), "/site.css".to_string());
+        let page_urls = Arc::new(page_urls);
+
+        let (raw, html) = (Stash::new("raw"), Stash::new("html"));
+        let mut options = LiquidOptions::default();
+        options.tags.insert("link".to_string(), link_tag(page_urls.clone(), "/base".to_string()));
+        options.tags.insert("post_url".to_string(), post_url_tag(page_urls, "/base".to_string()));
+        options.blocks.insert("highlight".to_string(), highlight_block(raw.clone(), html.clone()));
+
+        let template = ::liquid::parse(&extract_raw(source, &raw), options)?;
+        let rendered = template.render(&mut Context::new())?.unwrap_or_default();
+
+        Ok(html.restore(&raw.restore(&rendered)))
+    }
+
+    #[test]
+    fn it_links_to_pages_in_the_source_directory() {
+        assert_eq!("/base/docs/intro.html /base/site.css", render("{% link docs/intro.md %} {% link \"css/site.css\" %}").unwrap());
+        assert!(render("{% link docs/missing.md %}").unwrap_err().to_string().contains("no `docs/missing.md`"));
+    }
+
+    #[test]
+    fn it_links_to_posts_by_name() {
+        assert_eq!("/base/blog/2017-08-01-hello.html", render("{% post_url 2017-08-01-hello %}").unwrap());
+        assert_eq!("/base/blog/2017-08-01-hello.html", render("{% post_url blog/2017-08-01-hello %}").unwrap());
+        assert!(render("{% post_url 2017-08-02-goodbye %}").is_err());
+    }
+
+    #[test]
+    fn it_highlights_code() {
+        let rendered = render("{% assign x = 1 %}{% highlight rust %}\nfn main() { let v: Vec<u8> = vec![{{ x }}]; }\n{% endhighlight %}").unwrap();
+        assert_eq!("<figure class=\"highlight\"><pre><code class=\"language-rust\" data-lang=\"rust\">fn main() { let v: Vec&lt;u8&gt; = vec![1]; }</code></pre></figure>", rendered);
+
+        let numbered = render("{% highlight sh linenos %}\nls\n\npwd\n{% endhighlight %}").unwrap();
+        assert!(numbered.contains("<span class=\"lineno\">1</span>ls\n<span class=\"lineno\">2</span>\n<span class=\"lineno\">3</span>pwd"));
+        assert!(render("{% highlight %}code{% endhighlight %}").is_err());
+    }
+
+    #[test]
+    fn it_outputs_raw_blocks_untouched() {
+        assert_eq!("{{ not_rendered }} {% if x %} 2", render("{% raw %}{{ not_rendered }} {% if x %}{% endraw %} {{ 1 | plus: 1 }}").unwrap());
+        assert_eq!("<figure class=\"highlight\"><pre><code class=\"language-liquid\" data-lang=\"liquid\">{{ x }}</code></pre></figure>",
+                   render("{% highlight liquid %}{% raw %}{{ x }}{% endraw %}{% endhighlight %}").unwrap());
+    }
+}
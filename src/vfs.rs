@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use time::Timespec;
+use walkdir::WalkDir;
+
+/// Where a site's source files are read from.
+pub trait FileSystem: Send + Sync {
+    /// Every file under `directory`, at any depth. A missing directory has no files.
+    fn files(&self, directory: &Path) -> Vec<PathBuf>;
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, io::Error>;
+
+    /// When the file was last modified, if that's known.
+    fn modified(&self, path: &Path) -> Option<Timespec>;
+
+    /// Whether the files are on disk, so tools like git can be pointed at them.
+    fn is_on_disk(&self) -> bool {
+        false
+    }
+
+    fn read_to_string(&self, path: &Path) -> Result<String, io::Error> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{} isn't valid UTF-8", path.display())))
+    }
+}
+
+/// The real filesystem.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiskFileSystem;
+
+impl FileSystem for DiskFileSystem {
+    fn files(&self, directory: &Path) -> Vec<PathBuf> {
+        WalkDir::new(directory)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.path().to_path_buf())
+            .collect()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, io::Error> {
+        fs::read(path)
+    }
+
+    fn modified(&self, path: &Path) -> Option<Timespec> {
+        fs::metadata(path).ok()?.modified().ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| Timespec::new(since_epoch.as_secs() as i64, since_epoch.subsec_nanos() as i32))
+    }
+
+    fn is_on_disk(&self) -> bool {
+        true
+    }
+}
+
+/// Files held in memory, for building a site that isn't on disk, such as unsaved edits or a test.
+/// Paths are used as they're given, so look files up the same way they were added, e.g. with
+/// the config's `source` directory as the first component.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFileSystem {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, replacing any already at `path`.
+    pub fn insert<P: Into<PathBuf>, C: Into<Vec<u8>>>(&mut self, path: P, contents: C) -> &mut Self {
+        self.files.insert(path.into(), contents.into());
+        self
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Option<Vec<u8>> {
+        self.files.remove(path)
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn files(&self, directory: &Path) -> Vec<PathBuf> {
+        self.files.keys().filter(|path| path.starts_with(directory)).cloned().collect()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, io::Error> {
+        self.files.get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} doesn't exist", path.display())))
+    }
+
+    fn modified(&self, _path: &Path) -> Option<Timespec> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_lists_and_reads_files_in_memory() {
+        let mut file_system = MemoryFileSystem::new();
+        file_system.insert("pages/index.md", "Home")
+            .insert("pages/blog/hello.md", "Hello")
+            .insert("pages-old/index.md", "Old");
+
+        assert_eq!(vec![PathBuf::from("pages/blog/hello.md"), PathBuf::from("pages/index.md")], file_system.files(Path::new("pages")));
+        assert!(file_system.files(Path::new("pages/_layouts")).is_empty());
+        assert_eq!("Hello", file_system.read_to_string(Path::new("pages/blog/hello.md")).expect("Read file"));
+        assert_eq!(io::ErrorKind::NotFound, file_system.read(Path::new("pages/missing.md")).unwrap_err().kind());
+        assert_eq!(None, file_system.modified(Path::new("pages/index.md")));
+    }
+
+    #[test]
+    fn it_reads_files_on_disk() {
+        let file_system = DiskFileSystem;
+        let files = file_system.files(Path::new("tests/fixtures/layouts-and-data/_data"));
+
+        assert_eq!(2, files.len());
+        assert!(file_system.modified(Path::new("tests/fixtures/layouts-and-data/index.md")).is_some());
+        assert!(file_system.files(Path::new("tests/fixtures/missing")).is_empty());
+    }
+}
@@ -0,0 +1,18 @@
+# Adds a page listing every page's title, shouts each title and marks the rendered HTML.
+import json
+import sys
+
+request = json.load(sys.stdin)
+hook = request["hook"]
+
+if hook == "generate":
+    titles = sorted(page["front_matter"]["title"] for page in request["pages"])
+    content = "---\ntitle: Titles\n---\n" + "".join("- {}\n".format(title) for title in titles)
+    json.dump({"pages": [{"path": "titles.md", "content": content}]}, sys.stdout)
+elif hook == "before_render":
+    front_matter = request["page"]["front_matter"]
+    front_matter["title"] = front_matter["title"].upper()
+    json.dump({"front_matter": front_matter}, sys.stdout)
+elif hook == "after_render":
+    html = request["html"] + "<!-- {} in {} -->\n".format(request["page"]["url"], request["site"]["title"])
+    json.dump({"html": html}, sys.stdout)
@@ -1,4 +1,5 @@
 extern crate aluminum;
+extern crate liquid;
 extern crate hyper;
 extern crate walkdir;
 extern crate tempdir;
@@ -6,24 +7,35 @@ extern crate tempdir;
 extern crate difference;
 
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
 use std::path::Path;
-use std::fs::File;
+use std::fs::{DirBuilder, File};
 use std::thread;
+use std::sync::{mpsc, Arc};
 
 use hyper::Client;
+use hyper::client::RedirectPolicy;
+use hyper::header::{Connection, ContentLength, ContentRange, ContentRangeSpec, ETag, IfModifiedSince, IfNoneMatch, LastModified,
+                    Location, Range};
 use walkdir::WalkDir;
 use tempdir::TempDir;
 
 use aluminum::commands;
 use aluminum::config;
-
-fn run_create_tests(test_name: &str) -> Result<(), io::Error> {
+use aluminum::converters::Converter;
+use aluminum::extensions::TemplateExtensions;
+use aluminum::generation::Page;
+use aluminum::plugins::Plugin;
+use aluminum::site::Site;
+use aluminum::vfs::MemoryFileSystem;
+
+fn run_create_tests(test_name: &str, config_format: config::ConfigFormat) -> Result<(), io::Error> {
     let target = format!("tests/target/{}/", test_name);
 
     let tempdir = TempDir::new(test_name).expect("Couldn't create temporary directory");
 
-    let result = commands::new_project(tempdir.path().to_str().expect("Couldn't convert path to str"));
+    let result = commands::new_project(tempdir.path().to_str().expect("Couldn't convert path to str"), config_format);
 
     if result.is_ok() {
         let walkdir = WalkDir::new(&target).into_iter().filter_map(|e| e.ok());
@@ -52,74 +64,89 @@ fn run_create_tests(test_name: &str) -> Result<(), io::Error> {
     result
 }
 
-fn run_build_tests(test_name: &str, config_options: Vec<String>) -> Result<(), io::Error> {
-    let target = format!("tests/target/{}/", test_name);
+/// A build of `tests/fixtures/<fixture>` that's checked against `tests/target/<target>`. Tests set
+/// the fields they need over `BuildTest::new`.
+struct BuildTest<'a> {
+    fixture: &'a str,
+    target: &'a str,
+    config: config::Config,
+    extensions: TemplateExtensions,
+    plugins: Vec<Box<dyn Plugin>>,
+}
 
-    let mut config = config::Config::default();
-    let tempdir = TempDir::new(test_name).expect("Failed to create temporary directory under test");
+impl<'a> BuildTest<'a> {
+    /// Builds `fixture` with the default config and compares it to the target of the same name.
+    fn new(fixture: &'a str) -> Self {
+        BuildTest {
+            fixture: fixture,
+            target: fixture,
+            config: config::Config::default(),
+            extensions: TemplateExtensions::new(),
+            plugins: Vec::new(),
+        }
+    }
 
-    config.source_dir = format!("tests/fixtures/{}", test_name);
-    config.output_dir = tempdir.path().to_str().expect("Can't convert to string").to_string();
-    config.markdown_options = config_options;
+    fn run(self) -> Result<(), io::Error> {
+        let BuildTest { fixture, target, mut config, extensions, plugins } = self;
+        let tempdir = TempDir::new(target).expect("Failed to create temporary directory under test");
+        let target = format!("tests/target/{}/", target);
 
-    let result = commands::build_project(&config);
+        config.source_dir = format!("tests/fixtures/{}", fixture);
+        config.output_dir = tempdir.path().to_str().expect("Can't convert to string").to_string();
 
-    if result.is_ok() {
-        let target_files = WalkDir::new(&target)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file());
-
-        for target_file in target_files {
-            let tmp_path = target_file.path().strip_prefix(&target).expect("Couldn't get tmp_file path");
-
-            let mut expected = String::new();
-            File::open(&target_file.path())
-                .expect("Couldn't open expected file")
-                .read_to_string(&mut expected)
-                .expect("Couldn't read to string.");
-
-            let mut actual = String::new();
-            File::open(&Path::new(&config.output_dir).join(tmp_path))
-                .expect("Couldn't open actual file")
-                .read_to_string(&mut actual)
-                .expect("Couldn't read to string.");
-
-            assert_diff!(&expected, &actual, " ", 0);
-        }
+        let result = commands::build_project_with_plugins(&config, &extensions, plugins);
+
+        if result.is_ok() {
+            let target_files = WalkDir::new(&target)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file());
+
+            for target_file in target_files {
+                let tmp_path = target_file.path().strip_prefix(&target).expect("Couldn't get tmp_file path");
 
-        let temp_files = WalkDir::new(&tempdir)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file());
+                let mut expected = String::new();
+                File::open(&target_file.path())
+                    .expect("Couldn't open expected file")
+                    .read_to_string(&mut expected)
+                    .expect("Couldn't read to string.");
 
-        for temp_file in temp_files {
-            let target_path = Path::new(&target).join(&temp_file.path().strip_prefix(&tempdir).expect("Unable to strip prefix"));
+                let mut actual = String::new();
+                File::open(&Path::new(&config.output_dir).join(tmp_path))
+                    .expect("Couldn't open actual file")
+                    .read_to_string(&mut actual)
+                    .expect("Couldn't read to string.");
 
-            File::open(&target_path).expect(&format!("File {:?} should not be copied in the build command", temp_file));
+                assert_diff!(&expected, &actual, " ", 0);
+            }
+
+            let temp_files = WalkDir::new(&tempdir)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file());
+
+            for temp_file in temp_files {
+                let target_path = Path::new(&target).join(&temp_file.path().strip_prefix(&tempdir).expect("Unable to strip prefix"));
+
+                File::open(&target_path).expect(&format!("File {:?} should not be copied in the build command", temp_file));
+            }
         }
-    }
 
-    tempdir.close().ok().expect("Failed to close temp dir");
+        tempdir.close().ok().expect("Failed to close temp dir");
 
-    result
+        result
+    }
 }
 
 fn run_serve_tests(test_name: &str, mut config: config::Config, expected_status: hyper::status::StatusCode) -> Result<(), io::Error> {
     let target = format!("tests/target/{}/", test_name);
     let tempdir = TempDir::new(test_name).expect("Failed to create temporary directory under test");
-    let port = config.port.clone();
+    let port = config.port;
 
     config.source_dir = format!("tests/fixtures/{}", test_name);
     config.output_dir = tempdir.path().to_str().expect("Can't convert tempdir path to string").to_string();
 
-    thread::spawn(move || {
-        commands::serve(&config).expect("Serve Command");
-    });
-
-    /* FIXME: This is a dirty hack to make sure the server is started before running the request and
-        FIXME: making the proper assertions. I don't like this one bit... */
-    thread::sleep(std::time::Duration::from_millis(250));
+    let _server = start_server(&config);
 
     // Walk through _site dir, check file contents against HTTP served body
     let target_files = WalkDir::new(&target)
@@ -151,29 +178,345 @@ fn run_serve_tests(test_name: &str, mut config: config::Config, expected_status:
     Ok(())
 }
 
+fn send_raw_get_request(port: u16, path: &str) -> String {
+    // Sent by hand since hyper's client normalizes dot segments out of the URL before sending it
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).expect("Connecting to server");
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).expect("Sending raw request");
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("Reading raw response");
+
+    response.lines().next().unwrap_or("").to_string()
+}
+
+fn start_server(config: &config::Config) -> commands::DevServer {
+    commands::build_project(config).expect("Building site to serve");
+
+    commands::DevServer::start(config).expect("Starting server")
+}
+
+fn serve_fixture(fixture_name: &str, port: u16) -> (TempDir, commands::DevServer) {
+    let tempdir = TempDir::new(fixture_name).expect("Failed to create the directory under test");
+
+    let mut config = config::Config::default();
+    config.source_dir = format!("tests/fixtures/{}", fixture_name);
+    config.output_dir = tempdir.path().to_str().expect("Could not convert path to string").to_string();
+    config.port = port;
+
+    let server = start_server(&config);
+
+    (tempdir, server)
+}
+
 #[test]
 fn it_creates_a_new_project() {
-    run_create_tests("new-project").expect("Project creation error");
+    run_create_tests("new-project", config::ConfigFormat::Yaml).expect("Project creation error");
+}
+
+#[test]
+fn it_creates_a_new_project_with_a_toml_config() {
+    run_create_tests("new-project-toml", config::ConfigFormat::Toml).expect("Project creation error");
 }
 
 #[test]
 fn it_builds_a_default_project() {
-    run_build_tests("default-project", Vec::new()).expect("Failed to build a default project");
+    BuildTest::new("default-project").run().expect("Failed to build a default project");
 }
 
 #[test]
 fn it_ignores_files_with_underscores_when_building_the_project() {
-    run_build_tests("underscore-files", vec![]).expect("Failed to ignore underscored files");
+    BuildTest::new("underscore-files").run().expect("Failed to ignore underscored files");
 }
 
 #[test]
 fn it_copies_all_files_regardless_of_extension() {
-    run_build_tests("all-files", vec![]).expect("Failed to copy all files");
+    BuildTest::new("all-files").run().expect("Failed to copy all files");
+}
+
+#[test]
+fn it_leaves_out_drafts_future_and_expired_pages() {
+    BuildTest::new("drafts-and-future").run().expect("Failed to leave out unpublished pages");
+}
+
+#[test]
+fn it_includes_drafts_and_future_pages_when_asked() {
+    let mut config = config::Config::default();
+    config.drafts = true;
+    config.future = true;
+
+    BuildTest { target: "drafts-and-future-included", config: config, ..BuildTest::new("drafts-and-future") }.run()
+        .expect("Failed to include drafts and future pages");
+}
+
+#[test]
+fn it_applies_front_matter_defaults() {
+    let config = config::Config::from_string("defaults:\n\
+        - values:\n    author: platform-team\n\
+        - scope:\n    type: docs\n  values:\n    layout: doc\n".to_string()).expect("Parse config");
+
+    BuildTest { config: config, ..BuildTest::new("front-matter-defaults") }.run()
+        .expect("Failed to apply front matter defaults");
+}
+
+#[test]
+fn it_renders_link_post_url_highlight_and_raw_tags() {
+    let config = config::Config::from_string("base_path: /docs\ntitle: Aluminum".to_string()).expect("Parse config");
+
+    BuildTest { config: config, ..BuildTest::new("tags") }.run().expect("Failed to render custom tags");
+}
+
+#[test]
+fn it_fails_the_build_on_a_broken_link() {
+    let error = BuildTest::new("broken-link").run().expect_err("Broken links should fail the build");
+
+    assert!(error.to_string().contains("docs/guide.md"));
+}
+
+struct Generator;
+
+impl liquid::Renderable for Generator {
+    fn render(&self, _context: &mut liquid::Context) -> Result<Option<String>, liquid::Error> {
+        Ok(Some("Aluminum".to_string()))
+    }
+}
+
+#[test]
+fn it_renders_pages_with_custom_filters_and_tags() {
+    let mut extensions = TemplateExtensions::new();
+    extensions
+        .add_filter("shout", |input, _args| Ok(liquid::Value::Str(format!("{}!", input.to_string().to_uppercase()))))
+        .add_tag("generator", |_name, _arguments, _options| Ok(Box::new(Generator)));
+
+    BuildTest { extensions: extensions, ..BuildTest::new("extensions") }.run()
+        .expect("Failed to render custom filters and tags");
+}
+
+#[test]
+fn it_writes_a_sitemap_and_feed_with_the_built_in_plugins() {
+    let config = config::Config::from_string("title: Aluminum\nbase_url: https://example.com\nplugins: [sitemap, feed]".to_string())
+        .expect("Parse config");
+
+    BuildTest { config: config, ..BuildTest::new("plugins") }.run().expect("Failed to run the built-in plugins");
+}
+
+#[test]
+fn it_rejects_unknown_plugins() {
+    let config = config::Config::from_string("plugins: [sitemaps]".to_string()).expect("Parse config");
+    let error = BuildTest { config: config, ..BuildTest::new("plugins") }.run().expect_err("Unknown plugins should fail the build");
+
+    assert!(error.to_string().contains("Unknown plugin `sitemaps`"));
+}
+
+struct Shouting;
+
+impl Converter for Shouting {
+    fn convert(&self, contents: &str, _page: &Page) -> Result<String, io::Error> {
+        Ok(format!("<p>{}</p>\n", contents.trim().to_uppercase()))
+    }
+}
+
+#[test]
+fn it_converts_pages_by_extension() {
+    let mut extensions = TemplateExtensions::new();
+    extensions.add_converter("shout", Shouting);
+
+    BuildTest { extensions: extensions, ..BuildTest::new("content-formats") }.run()
+        .expect("Failed to build content formats")
+}
+
+/// Clears the built-in plugins, adds a page listing every titled page, prefixes each page with its
+/// title and marks the rendered HTML. Fails in `after_build` if asked to.
+struct ArchivePlugin {
+    fail_after_build: bool,
+}
+
+impl Plugin for ArchivePlugin {
+    fn name(&self) -> &str {
+        "archive"
+    }
+
+    fn after_config(&mut self, config: &mut config::Config) -> Result<(), io::Error> {
+        config.plugins.clear();
+        Ok(())
+    }
+
+    fn generate(&mut self, site: &mut Site) -> Result<(), io::Error> {
+        let mut titles: Vec<_> = site.pages.iter().filter_map(|page| page.front_matter["title"].as_str().map(|title| title.to_string())).collect();
+        titles.sort();
+
+        site.add_page("archive/index.md", &format!("---\ntitle: Archive\n---\n{}", titles.join(", ")))
+    }
+
+    fn before_render(&mut self, page: &mut Page) -> Result<(), io::Error> {
+        page.contents = format!("{{{{ title }}}}: {}", page.contents);
+        Ok(())
+    }
+
+    fn after_render(&mut self, page: &Page, html: &mut String) -> Result<(), io::Error> {
+        html.push_str(&format!("<!-- {} -->\n", page.url()));
+        Ok(())
+    }
+
+    fn after_build(&mut self, _site: &Site, output_dir: &Path) -> Result<(), io::Error> {
+        if self.fail_after_build {
+            assert!(output_dir.join("archive/index.html").exists());
+            return Err(io::Error::new(io::ErrorKind::Other, "stop here"));
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn it_runs_plugin_hooks_during_the_build() {
+    let config = config::Config::from_string("plugins: [sitemap]".to_string()).expect("Parse config");
+    let plugins: Vec<Box<dyn Plugin>> = vec![Box::new(ArchivePlugin { fail_after_build: false })];
+
+    BuildTest { target: "plugins-custom", config: config, plugins: plugins, ..BuildTest::new("plugins") }.run()
+        .expect("Failed to run plugin hooks");
+}
+
+#[test]
+fn it_stops_the_build_when_a_plugin_fails() {
+    let plugins: Vec<Box<dyn Plugin>> = vec![Box::new(ArchivePlugin { fail_after_build: true })];
+
+    let error = BuildTest { target: "plugins-custom", plugins: plugins, ..BuildTest::new("plugins") }.run()
+        .expect_err("The plugin's error should stop the build");
+    assert_eq!("Plugin `archive` failed: stop here", error.to_string());
+}
+
+#[test]
+fn it_runs_external_plugins() {
+    let mut config = config::Config::from_string("\
+title: Aluminum
+external_plugins:
+  - name: titles
+    command: python3 _plugins/titles.py
+    hooks: [generate, before_render, after_render]
+".to_string()).expect("Parse config");
+    config.root_dir = "tests/fixtures/external-plugins".to_string();
+
+    BuildTest { config: config, ..BuildTest::new("external-plugins") }.run().expect("Failed to run the external plugin");
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_reports_failing_external_plugins() {
+    let failing = config::Config::from_string("\
+external_plugins:
+  - name: broken
+    command: echo 'no titles here' >&2; exit 3
+    hooks: [generate]
+".to_string()).expect("Parse config");
+
+    let error = BuildTest { config: failing, ..BuildTest::new("external-plugins") }.run().expect_err("The plugin should fail");
+    assert!(error.to_string().starts_with("Plugin `broken` failed: generate: `echo 'no titles here' >&2; exit 3` exited with exit status: 3"));
+    assert!(error.to_string().ends_with("no titles here"));
+
+    let slow = config::Config::from_string("\
+external_plugins:
+  - name: slow
+    command: sleep 5
+    hooks: [after_render]
+    timeout: 1
+".to_string()).expect("Parse config");
+
+    let error = BuildTest { config: slow, ..BuildTest::new("external-plugins") }.run().expect_err("The plugin should time out");
+    assert_eq!(io::ErrorKind::TimedOut, error.kind());
+    assert!(error.to_string().contains("timed out after 1s"));
+}
+
+#[test]
+fn it_wraps_pages_in_layouts_and_exposes_data_files() {
+    let config = config::Config::from_string("title: Aluminum".to_string()).expect("Parse config");
+
+    BuildTest { config: config, ..BuildTest::new("layouts-and-data") }.run().expect("Failed to build with layouts and data");
+}
+
+#[test]
+fn it_renders_a_single_file() {
+    let mut config = config::Config::from_string("title: Aluminum".to_string()).expect("Parse config");
+    config.source_dir = "tests/fixtures/layouts-and-data".to_string();
+
+    let html = commands::render_file(&config, Path::new("tests/fixtures/layouts-and-data/blog/hello.md")).expect("Render file");
+
+    let mut expected = String::new();
+    File::open("tests/target/layouts-and-data/blog/hello.html").and_then(|mut file| file.read_to_string(&mut expected)).expect("Read target");
+    assert_eq!(expected, html);
+
+    let error = commands::render_file(&config, Path::new("tests/fixtures/scripts/index.md")).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+}
+
+#[test]
+fn it_renders_a_single_file_without_parsing_the_other_pages() {
+    let tempdir = TempDir::new("render-file").expect("Failed to create the directory under test");
+    let pages = tempdir.path().join("pages");
+    DirBuilder::new().recursive(true).create(pages.join("blog")).expect("Create pages");
+    write!(File::create(pages.join("broken.md")).expect("Create page"), "---\ntitle: [unclosed\n---\nBroken").expect("Write page");
+    write!(File::create(pages.join("blog/hello.md")).expect("Create page"), "---\ntitle: Hello\n---\n*{{{{ title }}}}*, see [this]({{% link broken.md %}})")
+        .expect("Write page");
+
+    let mut config = config::Config::default();
+    config.source_dir = pages.to_str().expect("Could not convert path to string").to_string();
+
+    let html = commands::render_file(&config, &pages.join("blog/hello.md")).expect("Render file");
+    assert_eq!("<p><em>Hello</em>, see <a href=\"/broken.html\">this</a></p>\n", html);
+}
+
+#[test]
+fn it_renders_a_site_from_files_in_memory() {
+    let mut file_system = MemoryFileSystem::new();
+    file_system.insert("pages/_layouts/default.html", "<main>{{ content }}</main>")
+        .insert("pages/_data/authors.json", "{\"lead\": \"Ada\"}")
+        .insert("pages/index.md", "---\nlayout: default\n---\n[About]({% link about.md %}) by {{ site.data.authors.lead }}")
+        .insert("pages/about.md", "Unsaved *edits*")
+        .insert("pages/style.css", "p {}");
+
+    let config = config::Config { output_dir: "/nonexistent/output".to_string(), ..Default::default() };
+
+    let site = Site::load_from_file_system(&config, &TemplateExtensions::new(), Arc::new(file_system)).expect("Load site");
+    let output = site.render().expect("Render site");
+
+    assert_eq!(vec!["about.html", "index.html", "style.css"], output.keys().collect::<Vec<_>>());
+    assert_eq!("<main><p><a href=\"/about.html\">About</a> by Ada</p>\n</main>", String::from_utf8_lossy(&output["index.html"]));
+    assert_eq!("<p>Unsaved <em>edits</em></p>\n", String::from_utf8_lossy(&output["about.html"]));
+    assert_eq!(b"p {}".to_vec(), output["style.css"]);
+}
+
+#[test]
+fn it_builds_a_project_from_files_in_memory_with_its_plugins_and_scripts() {
+    let mut file_system = MemoryFileSystem::new();
+    file_system.insert("pages/index.md", "Unsaved *edits*")
+        .insert("pages/_scripts/mark.rhai", "fn transform_html(html, page) { html + \"<!-- \" + page.path + \" -->\\n\" }");
+
+    let tempdir = TempDir::new("memory-build").expect("Failed to create the directory under test");
+    let mut config = config::Config::from_string("base_url: https://example.com\nplugins: [sitemap]".to_string()).expect("Parse config");
+    config.output_dir = tempdir.path().to_str().expect("Could not convert path to string").to_string();
+
+    commands::build_project_from_file_system(&config, &TemplateExtensions::new(), Vec::new(), Arc::new(file_system))
+        .expect("Build from memory");
+
+    let read = |name: &str| {
+        let mut contents = String::new();
+        File::open(tempdir.path().join(name)).and_then(|mut file| file.read_to_string(&mut contents)).expect("Read output");
+        contents
+    };
+    assert_eq!("<p>Unsaved <em>edits</em></p>\n<!-- index.md -->\n", read("index.html"));
+    assert!(read("sitemap.xml").contains("<loc>https://example.com/index.html</loc>"));
+}
+
+#[test]
+fn it_runs_scripts_from_the_scripts_directory() {
+    BuildTest::new("scripts").run().expect("Failed to run the site's scripts");
 }
 
 #[test]
 fn it_builds_a_project_with_footnote_and_table_support() {
-    run_build_tests("enhanced-project", vec!["tables".to_string(), "footnotes".to_string()]).expect("Failed to build a project with footnote and table support");
+    let mut config = config::Config::default();
+    config.markdown_options = vec!["tables".to_string(), "footnotes".to_string()];
+
+    BuildTest { config: config, ..BuildTest::new("enhanced-project") }.run().expect("Failed to build a project with footnote and table support");
 }
 
 #[test]
@@ -207,7 +550,7 @@ fn it_spins_up_a_web_server_on_serve_command() {
 #[test]
 fn the_port_number_can_be_changed() {
     let mut config = config::Config::default();
-    config.port = "4001".to_string();
+    config.port = 4001;
 
     run_serve_tests("serve-simple-project", config, hyper::Ok).expect("Could not change the port number");
 }
@@ -215,7 +558,7 @@ fn the_port_number_can_be_changed() {
 #[test]
 fn it_returns_a_404_when_the_route_is_invalid() {
     let mut config = config::Config::default();
-    config.port = "4002".to_string();
+    config.port = 4002;
 
     run_serve_tests("serve-bad-route", config, hyper::NotFound).expect("Did not error out on a bad request");
 }
@@ -223,7 +566,7 @@ fn it_returns_a_404_when_the_route_is_invalid() {
 #[test]
 fn it_hits_every_route_in_the_pages_directory() {
     let mut config = config::Config::default();
-    config.port = "4003".to_string();
+    config.port = 4003;
 
     run_serve_tests("serve-project-multiple-pages", config, hyper::Ok).expect("Could not serve multiple pages");
 }
@@ -233,27 +576,30 @@ fn it_builds_the_project_before_serving_the_site() {
     let base_dir = "tests/fixtures/serve-project-non-built".to_string();
     let tempdir = TempDir::new("build-before-serve").expect("Couldn't create temporary directory under test");
     let site_path = tempdir.path().to_str().expect("Can't convert tempdir path to string").to_string();
-    let port = "4004".to_string();
+    let port = 4004;
 
     let mut config = config::Config::default();
     config.source_dir = base_dir.clone();
     config.output_dir = site_path.clone();
-    config.port = port.clone();
+    config.port = port;
 
     assert!(!Path::new(&(site_path.clone() + "/index.html")).exists());
 
-    thread::spawn(move || {
-        commands::serve(&config).expect("Serve");
-    });
+    let (shutdown, shutdown_receiver) = mpsc::channel();
+    let server = thread::spawn(move || commands::serve_until(&config, shutdown_receiver));
 
     let server_addr = format!("http://localhost:{}/index.html", port);
     let client = Client::new();
 
+    // The project has to be built before serve_until binds, so there's nothing to wait on but time
     thread::sleep(std::time::Duration::from_millis(250));
     let response = client.get(server_addr.as_str()).send().expect("Sending Client Request");
 
     assert_eq!(hyper::Ok, response.status);
 
+    shutdown.send(()).expect("Shutting down server");
+    server.join().expect("Server thread").expect("Serve");
+
     let mut clean_config = config::Config::default();
     clean_config.output_dir = site_path.clone();
 
@@ -266,38 +612,107 @@ fn it_returns_400_on_a_bad_request() {
     let target_dir = TempDir::new("serve-simple-project-built")
         .expect("Failed to create the directory under test");
 
-    let port = "4005".to_string();
+    let port = 4005;
 
     let mut config = config::Config::default();
     config.source_dir = "tests/fixtures/serve-simple-project-built".to_string();
     config.output_dir = target_dir.path().to_str().expect("Could not convert path to string").to_string();
-    config.port = port.clone();
+    config.port = port;
 
-    thread::spawn(move || {
-        commands::serve(&config).expect("Serve");
-    });
+    let _server = start_server(&config);
 
-    // This is a dirty hack to make sure the server is started before running the request and
-    // making the proper assertions. I don't like this one bit...
     let server_addr = format!("http://localhost:{}/pages/index.html", port);
     let client = Client::new();
 
-    thread::sleep(std::time::Duration::from_millis(250));
     let response = client.post(server_addr.as_str()).send().expect("Send Bad Request");
 
     assert_eq!(hyper::BadRequest, response.status);
 }
 
 #[test]
-#[should_panic]
-fn it_panics_on_invalid_server_connection() {
-    let targetdir = TempDir::new("panics-on-invalid-conn").expect("Couldn't create directory under test");
+fn it_returns_400_when_the_path_escapes_the_output_directory() {
+    let tempdir = TempDir::new("serve-path-traversal").expect("Failed to create the directory under test");
+    let port = 4007;
+
+    File::create(tempdir.path().join("_config.yml"))
+        .expect("Couldn't create file outside of the output directory")
+        .write_all(b"secret: value")
+        .expect("Couldn't write file outside of the output directory");
+
+    let mut config = config::Config::default();
+    config.source_dir = "tests/fixtures/serve-simple-project-built".to_string();
+    config.output_dir = tempdir.path().join("_site").to_str().expect("Could not convert path to string").to_string();
+    config.port = port;
+
+    let _server = start_server(&config);
+
+    for path in &["/../_config.yml", "/pages/../../_config.yml", "/%2e%2e/_config.yml", "/pages/..%2f..%2f_config.yml"] {
+        let status_line = send_raw_get_request(port, path);
+
+        assert!(status_line.contains("400"), "Expected 400 for {}, got {}", path, status_line);
+    }
+}
+
+#[test]
+fn it_serves_percent_encoded_paths() {
+    let mut config = config::Config::default();
+    config.port = 4008;
+
+    run_serve_tests("serve-encoded-path", config, hyper::Ok).expect("Could not serve a percent-encoded path");
+}
+
+#[test]
+fn it_ignores_query_strings_and_fragments() {
+    let target_dir = TempDir::new("serve-query-string").expect("Failed to create the directory under test");
+    let port = 4009;
+
+    let mut config = config::Config::default();
+    config.source_dir = "tests/fixtures/serve-simple-project-built".to_string();
+    config.output_dir = target_dir.path().to_str().expect("Could not convert path to string").to_string();
+    config.port = port;
+
+    let _server = start_server(&config);
+
+    let status_line = send_raw_get_request(port, "/pages/index.html?version=2#section");
+
+    assert!(status_line.contains("200"), "Expected 200, got {}", status_line);
+}
+
+#[test]
+fn it_returns_an_error_when_the_address_is_in_use() {
+    let targetdir = TempDir::new("address-in-use").expect("Couldn't create directory under test");
+    let _listener = TcpListener::bind("127.0.0.1:4014").expect("Couldn't occupy the port under test");
 
     let mut config = config::Config::default();
     config.source_dir = "tests/fixtures/serve-simple-project-built".to_string();
     config.output_dir = targetdir.path().to_str().expect("Could not get str from path").to_string();
-    config.port = "65536".to_string();
-    commands::serve(&config).expect("Serve");
+    config.port = 4014;
+
+    let error = commands::DevServer::start(&config).err().expect("Serving on an occupied port should fail");
+
+    assert_eq!(io::ErrorKind::AddrInUse, error.kind());
+    assert!(error.to_string().contains("127.0.0.1:4014"));
+}
+
+#[test]
+fn it_serves_on_an_ipv6_host() {
+    let targetdir = TempDir::new("ipv6-host").expect("Couldn't create directory under test");
+
+    let mut config = config::Config::default();
+    config.source_dir = "tests/fixtures/serve-simple-project-built".to_string();
+    config.output_dir = targetdir.path().to_str().expect("Could not get str from path").to_string();
+    config.host = "::1".to_string();
+    config.port = 0;
+
+    let server = start_server(&config);
+    let mut stream = TcpStream::connect(server.local_addr()).expect("Connecting over IPv6");
+    write!(stream, "GET /pages/index.html HTTP/1.1\r\nHost: [::1]\r\nConnection: close\r\n\r\n").expect("Sending request");
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("Reading response");
+    assert!(response.starts_with("HTTP/1.1 200"));
+
+    server.shutdown().expect("Shutting down server");
 }
 
 #[test]
@@ -306,13 +721,11 @@ fn it_returns_the_index_page_as_the_root_route() {
     let tempdir = TempDir::new("default-project").expect("Failed to create temporary directory under test");
     let output_dir = tempdir.path().to_str().expect("Could not convert path to string").to_string();
 
-    config.port = "4006".to_string();
+    config.port = 4006;
     config.source_dir = "tests/target/default-project".to_string();
     config.output_dir = output_dir.clone();
 
-    thread::spawn(move || commands::serve(&config));
-
-    thread::sleep(std::time::Duration::from_millis(250));
+    let _server = start_server(&config);
 
     let client = Client::new();
     let mut response = client.get("http://localhost:4006").send().expect("Sending Client Request");
@@ -328,3 +741,137 @@ fn it_returns_the_index_page_as_the_root_route() {
     assert_diff!(&expected, &response_body, " ", 0);
     assert_eq!(hyper::Ok, response.status);
 }
+
+#[test]
+fn it_sends_validators_and_honors_if_none_match() {
+    let (_site, _server) = serve_fixture("serve-simple-project-built", 4010);
+    let url = "http://localhost:4010/pages/index.html";
+    let client = Client::new();
+
+    let response = client.get(url).header(Connection::close()).send().expect("Sending Client Request");
+    let entity_tag = response.headers.get::<ETag>().expect("ETag header").0.clone();
+
+    assert_eq!(hyper::Ok, response.status);
+    assert!(response.headers.has::<LastModified>());
+
+    let response = client.get(url)
+        .header(IfNoneMatch::Items(vec![entity_tag]))
+        .send()
+        .expect("Sending Conditional Request");
+
+    assert_eq!(hyper::status::StatusCode::NotModified, response.status);
+}
+
+#[test]
+fn it_honors_if_modified_since() {
+    let (_site, _server) = serve_fixture("serve-simple-project-built", 4011);
+    let url = "http://localhost:4011/pages/index.html";
+    let client = Client::new();
+
+    let response = client.get(url).header(Connection::close()).send().expect("Sending Client Request");
+    let last_modified = response.headers.get::<LastModified>().expect("Last-Modified header").0;
+
+    let response = client.get(url)
+        .header(IfModifiedSince(last_modified))
+        .send()
+        .expect("Sending Conditional Request");
+
+    assert_eq!(hyper::status::StatusCode::NotModified, response.status);
+}
+
+#[test]
+fn it_serves_byte_ranges() {
+    let (_site, _server) = serve_fixture("serve-simple-project-built", 4012);
+    let url = "http://localhost:4012/pages/index.html";
+    let client = Client::new();
+
+    let mut response = client.get(url)
+        .header(Range::bytes(0, 3))
+        .header(Connection::close())
+        .send()
+        .expect("Sending Range Request");
+
+    let mut response_body = String::new();
+    response.read_to_string(&mut response_body).expect("Response Body");
+
+    assert_eq!(hyper::status::StatusCode::PartialContent, response.status);
+    assert_eq!("<h1>", response_body);
+    assert_eq!(Some(&ContentRange(ContentRangeSpec::Bytes { range: Some((0, 3)), instance_length: Some(123) })),
+               response.headers.get::<ContentRange>());
+
+    let response = client.get(url)
+        .header(Range::bytes(1000, 2000))
+        .send()
+        .expect("Sending Unsatisfiable Range Request");
+
+    assert_eq!(hyper::status::StatusCode::RangeNotSatisfiable, response.status);
+}
+
+#[test]
+fn it_responds_to_head_requests_without_a_body() {
+    let (_site, _server) = serve_fixture("serve-simple-project-built", 4013);
+    let client = Client::new();
+
+    let mut response = client.head("http://localhost:4013/pages/index.html").send().expect("Sending HEAD Request");
+
+    let mut response_body = String::new();
+    response.read_to_string(&mut response_body).expect("Response Body");
+
+    assert_eq!(hyper::Ok, response.status);
+    assert_eq!(Some(&ContentLength(123)), response.headers.get::<ContentLength>());
+    assert!(response_body.is_empty());
+}
+
+#[test]
+fn it_keeps_serving_after_a_malformed_request() {
+    let (_site, _server) = serve_fixture("serve-simple-project-built", 4015);
+
+    let mut stream = TcpStream::connect("127.0.0.1:4015").expect("Connecting to server");
+    stream.write_all(b"NOT HTTP AT ALL\r\n\r\n").expect("Sending malformed request");
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("Reading raw response");
+    assert!(response.starts_with("HTTP/1.1 400"), "Expected 400, got {}", response);
+
+    let status_line = send_raw_get_request(4015, "/pages/index.html");
+
+    assert!(status_line.contains("200"), "Expected 200, got {}", status_line);
+}
+
+#[test]
+fn it_releases_the_port_on_shutdown() {
+    let (_site, server) = serve_fixture("serve-simple-project-built", 4016);
+
+    assert!(send_raw_get_request(4016, "/pages/index.html").contains("200"));
+
+    server.shutdown().expect("Shutting down server");
+
+    assert!(TcpStream::connect("127.0.0.1:4016").is_err());
+    TcpListener::bind("127.0.0.1:4016").expect("Port should be free after shutdown");
+}
+
+#[test]
+fn it_serves_the_site_under_the_base_path() {
+    let tempdir = TempDir::new("serve-base-path").expect("Failed to create the directory under test");
+
+    let mut config = config::Config::default();
+    config.source_dir = "tests/fixtures/serve-simple-project-built".to_string();
+    config.output_dir = tempdir.path().to_str().expect("Could not convert path to string").to_string();
+    config.base_path = "/docs".to_string();
+    config.port = 4017;
+
+    let _server = start_server(&config);
+
+    assert!(send_raw_get_request(4017, "/docs/pages/index.html").contains("200"));
+    assert!(send_raw_get_request(4017, "/pages/index.html").contains("404"));
+
+    let mut client = Client::new();
+    client.set_redirect_policy(RedirectPolicy::FollowNone);
+
+    for path in &["/", "/docs"] {
+        let response = client.get(&format!("http://localhost:4017{}", path)).send().expect("Sending Client Request");
+
+        assert_eq!(hyper::status::StatusCode::Found, response.status);
+        assert_eq!(Some(&Location("/docs/".to_string())), response.headers.get::<Location>());
+    }
+}